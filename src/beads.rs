@@ -0,0 +1,143 @@
+//! Beads drawn with shading from scratch, so no image of a bead is needed
+
+use std::array;
+
+use image::{Rgb, Rgba, RgbaImage};
+
+/// Settings for drawing beads
+#[derive(Debug, Clone, Copy)]
+pub struct BeadStyle {
+    /// Width and height of each bead in pixels
+    pub size: u32,
+    /// Colour of the pegboard showing between the beads, through their holes and through translucent beads,
+    /// transparent if not given
+    pub background: Option<Rgb<u8>>,
+}
+
+impl Default for BeadStyle {
+    fn default() -> Self {
+        BeadStyle {
+            size: 24,
+            background: None,
+        }
+    }
+}
+
+/// Radius of a bead relative to half its size
+const OUTER_RADIUS: f32 = 0.94;
+const HOLE_RADIUS: f32 = 0.4;
+/// Radius of the pegs of the pegboard relative to half the size of a bead
+const PEG_RADIUS: f32 = 0.28;
+/// How much darker the pegs are than the pegboard
+const PEG_SHADE: f32 = 0.8;
+/// How much of the background shows through translucent beads
+const TRANSLUCENCY: f32 = 0.45;
+/// Samples per pixel along each axis, to smooth the edges
+const SAMPLES: u32 = 4;
+
+/// How a pixel of a bead is lit, the same for all beads
+#[derive(Debug, Clone, Copy, Default)]
+struct Shade {
+    /// How much of the pixel the bead covers
+    coverage: f32,
+    /// Light falling on the covered part, multiplying the bead colour
+    light: f32,
+    /// White highlight reflected on the covered part
+    specular: f32,
+    /// How much of the pixel is covered by the peg below the bead
+    peg: f32,
+}
+
+/// Works out the shading of each pixel of a bead, row by row
+///
+/// The bead is a ring with a round cross section lit from the top left.
+fn shades(size: u32) -> Vec<Shade> {
+    let light = normalise([-0.45, -0.55, 0.7]);
+    // Halfway between the light and the view from straight above
+    let half_way = normalise([light[0], light[1], light[2] + 1.]);
+    let middle = (OUTER_RADIUS + HOLE_RADIUS) / 2.;
+    let half_width = (OUTER_RADIUS - HOLE_RADIUS) / 2.;
+    let samples = (SAMPLES * SAMPLES) as f32;
+
+    (0..size * size)
+        .map(|i| {
+            let (x, y) = (i % size, i / size);
+            let mut shade = Shade::default();
+            for s in 0..SAMPLES * SAMPLES {
+                let to_unit = |p: u32, offset: u32| {
+                    ((p * SAMPLES + offset) as f32 + 0.5) / (size * SAMPLES) as f32 * 2. - 1.
+                };
+                let (u, v) = (to_unit(x, s % SAMPLES), to_unit(y, s / SAMPLES));
+                let r = (u * u + v * v).sqrt();
+                if r < PEG_RADIUS {
+                    shade.peg += 1.;
+                }
+                if !(HOLE_RADIUS..=OUTER_RADIUS).contains(&r) {
+                    continue;
+                }
+                // The surface tilts from facing up in the middle of the ring to facing out at its edges
+                let t = ((r - middle) / half_width).clamp(-1., 1.);
+                let normal = [u / r * t, v / r * t, (1. - t * t).sqrt()];
+                shade.coverage += 1.;
+                shade.light += 0.35 + 0.75 * dot(normal, light).max(0.);
+                shade.specular += 0.6 * dot(normal, half_way).max(0.).powi(32);
+            }
+            if shade.coverage > 0. {
+                shade.light /= shade.coverage;
+                shade.specular /= shade.coverage;
+            }
+            shade.coverage /= samples;
+            shade.peg /= samples;
+            shade
+        })
+        .collect()
+}
+
+fn normalise([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Draws each bead as a shaded ring with a hole, `translucent` telling whether the bead at a position
+/// lets the background through
+pub fn draw_beads(
+    beads: &RgbaImage,
+    style: &BeadStyle,
+    translucent: impl Fn(u32, u32) -> bool + Sync,
+) -> RgbaImage {
+    let size = style.size.max(1);
+    let shades = shades(size);
+
+    RgbaImage::from_par_fn(beads.width() * size, beads.height() * size, |x, y| {
+        let (bx, by) = (x / size, y / size);
+        let shade = shades[((y % size) * size + x % size) as usize];
+
+        // What is seen where the bead doesn't cover
+        let behind = style.background.map(|Rgb(background)| {
+            let peg = 1. - (1. - PEG_SHADE) * shade.peg;
+            background.map(|c| c as f32 * peg)
+        });
+
+        let bead = beads.get_pixel(bx, by);
+        let mut coverage = shade.coverage * bead[3] as f32 / 255.;
+        if translucent(bx, by) {
+            coverage *= 1. - TRANSLUCENCY;
+        }
+        let colour: [f32; 3] =
+            array::from_fn(|i| bead[i] as f32 * shade.light + 255. * shade.specular);
+
+        let Rgba(out) = match behind {
+            Some(behind) => {
+                let [r, g, b] =
+                    array::from_fn(|i| behind[i] * (1. - coverage) + colour[i] * coverage);
+                Rgba([r, g, b, 255.])
+            }
+            None => Rgba([colour[0], colour[1], colour[2], 255. * coverage]),
+        };
+        Rgba(out.map(|c| c.round().clamp(0., 255.) as u8))
+    })
+}
@@ -0,0 +1,173 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+};
+
+use image::ImageError;
+
+#[derive(Debug)]
+pub enum PerlurError {
+    /// The image to convert could not be opened or decoded
+    OpenImage { path: PathBuf, source: ImageError },
+    /// The image to convert could not be read or decoded from standard input
+    ReadStdin { source: ImageError },
+    /// The bead image used when rendering beads could not be opened or decoded
+    OpenPerla { path: PathBuf, source: ImageError },
+    /// The resulting image could not be encoded or written
+    SaveImage { path: PathBuf, source: ImageError },
+    /// The resulting image could not be encoded or written to standard output
+    WriteStdout { source: ImageError },
+    /// The bead counts could not be written to the file
+    WriteCounts { path: PathBuf, source: io::Error },
+    /// The shopping list could not be written to the file
+    WriteShoppingList { path: PathBuf, source: io::Error },
+    /// The PDF chart could not be written to the file
+    WritePdf { path: PathBuf, source: io::Error },
+    /// The SVG image could not be written to the file
+    WriteSvg { path: PathBuf, source: io::Error },
+    /// The palette file could not be read
+    ReadPalette { path: PathBuf, source: io::Error },
+    /// A line in the palette file couldn't be parsed
+    InvalidPaletteLine {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// The palette file couldn't be parsed
+    InvalidPalette { path: PathBuf, reason: String },
+    /// Two colours in the palette file have the same name,
+    /// for binary formats the line is the number of the colour in the file
+    DuplicateColourName {
+        path: PathBuf,
+        line: usize,
+        first_line: usize,
+        name: Box<str>,
+    },
+    /// The palette file has no colours in it
+    EmptyPalette { path: PathBuf },
+    /// The inventory file could not be read
+    ReadInventory { path: PathBuf, source: io::Error },
+    /// A line in the inventory file couldn't be parsed
+    InvalidInventoryLine {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// There is no built-in palette with the name
+    UnknownBuiltinPalette { name: Box<str> },
+    /// A pattern for selecting palette colours didn't match any colour
+    UnmatchedColourPattern { pattern: Box<str> },
+    /// Selecting palette colours left none to use
+    NoColoursSelected,
+}
+
+pub type Result<T, E = PerlurError> = std::result::Result<T, E>;
+
+impl Display for PerlurError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerlurError::OpenImage { path, source } => {
+                write!(f, "could not open image {}: {source}", path.display())
+            }
+            PerlurError::ReadStdin { source } => {
+                write!(f, "could not read image from standard input: {source}")
+            }
+            PerlurError::OpenPerla { path, source } => {
+                write!(f, "could not open bead image {}: {source}", path.display())
+            }
+            PerlurError::SaveImage { path, source } => {
+                write!(f, "could not save image to {}: {source}", path.display())
+            }
+            PerlurError::WriteStdout { source } => {
+                write!(f, "could not write image to standard output: {source}")
+            }
+            PerlurError::WriteCounts { path, source } => {
+                write!(
+                    f,
+                    "could not write bead counts to {}: {source}",
+                    path.display()
+                )
+            }
+            PerlurError::WriteShoppingList { path, source } => {
+                write!(
+                    f,
+                    "could not write shopping list to {}: {source}",
+                    path.display()
+                )
+            }
+            PerlurError::WritePdf { path, source } => {
+                write!(f, "could not write PDF chart {}: {source}", path.display())
+            }
+            PerlurError::WriteSvg { path, source } => {
+                write!(f, "could not write SVG image {}: {source}", path.display())
+            }
+            PerlurError::ReadPalette { path, source } => {
+                write!(f, "could not read palette {}: {source}", path.display())
+            }
+            PerlurError::InvalidPaletteLine { path, line, reason } => {
+                write!(f, "{}:{line}: {reason}", path.display())
+            }
+            PerlurError::InvalidPalette { path, reason } => {
+                write!(f, "{}: {reason}", path.display())
+            }
+            PerlurError::DuplicateColourName {
+                path,
+                line,
+                first_line,
+                name,
+            } => write!(
+                f,
+                "{}:{line}: colour name `{name}` is already used on line {first_line}",
+                path.display()
+            ),
+            PerlurError::EmptyPalette { path } => {
+                write!(f, "palette {} has no colours", path.display())
+            }
+            PerlurError::ReadInventory { path, source } => {
+                write!(f, "could not read inventory {}: {source}", path.display())
+            }
+            PerlurError::InvalidInventoryLine { path, line, reason } => {
+                write!(f, "{}:{line}: {reason}", path.display())
+            }
+            PerlurError::UnknownBuiltinPalette { name } => write!(
+                f,
+                "there is no built-in palette called `{name}`, see `perlur palettes list` \
+                 or give the path of a palette file"
+            ),
+            PerlurError::UnmatchedColourPattern { pattern } => {
+                write!(f, "`{pattern}` does not match any colour in the palette")
+            }
+            PerlurError::NoColoursSelected => {
+                write!(f, "no colours are left in the palette after selecting")
+            }
+        }
+    }
+}
+
+impl Error for PerlurError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PerlurError::OpenImage { source, .. }
+            | PerlurError::ReadStdin { source }
+            | PerlurError::OpenPerla { source, .. }
+            | PerlurError::SaveImage { source, .. }
+            | PerlurError::WriteStdout { source } => Some(source),
+            PerlurError::WriteCounts { source, .. }
+            | PerlurError::WriteShoppingList { source, .. }
+            | PerlurError::WritePdf { source, .. }
+            | PerlurError::WriteSvg { source, .. }
+            | PerlurError::ReadPalette { source, .. }
+            | PerlurError::ReadInventory { source, .. } => Some(source),
+            PerlurError::InvalidPaletteLine { .. }
+            | PerlurError::InvalidPalette { .. }
+            | PerlurError::InvalidInventoryLine { .. }
+            | PerlurError::DuplicateColourName { .. }
+            | PerlurError::EmptyPalette { .. }
+            | PerlurError::UnknownBuiltinPalette { .. }
+            | PerlurError::UnmatchedColourPattern { .. }
+            | PerlurError::NoColoursSelected => None,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Grid lines and row and column numbers to use images of patterns as placement guides
+
+use image::{imageops::replace, Rgba, RgbaImage};
+
+use crate::font::{draw_text, text_width, GLYPH_HEIGHT};
+
+/// Settings for grids over images of patterns
+#[derive(Debug, Clone, Copy)]
+pub struct GridOptions {
+    /// Draw lines between the beads
+    pub lines: bool,
+    /// Every how many beads the lines are heavier
+    pub every: u32,
+    /// Number the rows and columns in margins along the top and left edges
+    pub coordinates: bool,
+}
+
+impl Default for GridOptions {
+    fn default() -> Self {
+        GridOptions {
+            lines: true,
+            every: 10,
+            coordinates: false,
+        }
+    }
+}
+
+/// Thickness of the heaviest grid lines in pixels
+const HEAVY_LINE: u32 = 3;
+
+/// How heavy a grid line is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LineWeight {
+    Thin,
+    Medium,
+    Heavy,
+}
+
+/// Weight of the line before bead `n`, heavy every `every` beads and medium halfway between when it's even
+pub(crate) fn line_weight(n: u32, every: u32) -> LineWeight {
+    let every = every.max(1);
+    if n.is_multiple_of(every) {
+        LineWeight::Heavy
+    } else if every.is_multiple_of(2) && every >= 4 && n.is_multiple_of(every / 2) {
+        LineWeight::Medium
+    } else {
+        LineWeight::Thin
+    }
+}
+
+/// Whether to number row or column `n`, counting from 1
+pub(crate) fn is_labelled(n: u32, every: u32) -> bool {
+    n == 1 || line_weight(n, every) > LineWeight::Thin
+}
+
+/// Draws a grid over an image of `beads` scaled up, adding margins with the row and column numbers
+/// if those are wanted
+pub fn overlay_grid(img: &RgbaImage, beads: &RgbaImage, options: &GridOptions) -> RgbaImage {
+    let (columns, rows) = beads.dimensions();
+    let cell = img.width() / columns.max(1);
+    let (grid_width, grid_height) = (columns * cell, rows * cell);
+
+    let scale = (cell / 12).max(1);
+    // Room for the outer lines, which are centred on the edges of the beads
+    let padding = if options.lines { HEAVY_LINE / 2 + 1 } else { 0 };
+    let (left, top) = if options.coordinates {
+        let widest = text_width(&rows.to_string());
+        (
+            (widest + 3) * scale + padding,
+            (GLYPH_HEIGHT + 3) * scale + padding,
+        )
+    } else {
+        (padding, padding)
+    };
+
+    let mut out = RgbaImage::from_pixel(
+        img.width() + left + padding,
+        img.height() + top + padding,
+        Rgba([255, 255, 255, 255]),
+    );
+    replace(&mut out, img, left as i64, top as i64);
+
+    if options.lines {
+        let style = |weight| match weight {
+            LineWeight::Thin => (1, Rgba([128, 128, 128, 255])),
+            LineWeight::Medium => (2, Rgba([64, 64, 64, 255])),
+            LineWeight::Heavy => (HEAVY_LINE as i64, Rgba([0, 0, 0, 255])),
+        };
+        for x in 0..=columns {
+            let (thickness, colour) = style(line_weight(x, options.every));
+            let line_x = (left + x * cell) as i64 - thickness / 2;
+            fill_rect(
+                &mut out,
+                line_x,
+                top as i64,
+                thickness,
+                grid_height as i64,
+                colour,
+            );
+        }
+        for y in 0..=rows {
+            let (thickness, colour) = style(line_weight(y, options.every));
+            let line_y = (top + y * cell) as i64 - thickness / 2;
+            fill_rect(
+                &mut out,
+                left as i64,
+                line_y,
+                grid_width as i64,
+                thickness,
+                colour,
+            );
+        }
+    }
+
+    if options.coordinates {
+        let black = Rgba([0, 0, 0, 255]);
+        for x in (1..=columns).filter(|&n| is_labelled(n, options.every)) {
+            let label = x.to_string();
+            let label_x =
+                (left + (x - 1) * cell + cell / 2) as i64 - (text_width(&label) * scale / 2) as i64;
+            draw_text(&mut out, label_x, scale as i64, scale, black, &label);
+        }
+        for y in (1..=rows).filter(|&n| is_labelled(n, options.every)) {
+            let label = y.to_string();
+            let label_x = (left - (text_width(&label) + 1) * scale) as i64 - scale as i64;
+            let label_y =
+                (top + (y - 1) * cell + cell / 2) as i64 - (GLYPH_HEIGHT * scale / 2) as i64;
+            draw_text(&mut out, label_x, label_y, scale, black, &label);
+        }
+    }
+    out
+}
+
+/// Fills a rectangle clipped to the image
+fn fill_rect(img: &mut RgbaImage, x: i64, y: i64, width: i64, height: i64, colour: Rgba<u8>) {
+    for py in y.max(0)..(y + height).min(img.height() as i64) {
+        for px in x.max(0)..(x + width).min(img.width() as i64) {
+            img.put_pixel(px as u32, py as u32, colour);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    error::{PerlurError, Result},
+    palette::{split_name, Palette},
+};
+
+/// How many beads of each colour are available
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    counts: HashMap<Box<str>, u32>,
+}
+
+impl Inventory {
+    pub fn new(counts: HashMap<Box<str>, u32>) -> Self {
+        Inventory { counts }
+    }
+    /// Reads an inventory file with a colour name followed by whitespace and a count on each line
+    ///
+    /// Names are written like in palette files and may be followed by a colon,
+    /// so the bead counts printed by perlur can be used as an inventory.
+    /// Blank lines and everything after a `#` are ignored.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| PerlurError::ReadInventory {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        let mut counts = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |reason| PerlurError::InvalidInventoryLine {
+                path: path.to_owned(),
+                line: i + 1,
+                reason,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, rest) = split_name(line).map_err(invalid)?;
+            let (count, rest) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+            let count: u32 = count
+                .parse()
+                .map_err(|_| invalid(format!("expected a bead count, found `{count}`")))?;
+            let rest = rest.trim_start();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(invalid(format!("unexpected `{rest}` after the count")));
+            }
+            // Listing a colour more than once, e.g. for several boxes, adds up the counts
+            *counts.entry(name.into()).or_insert(0) += count;
+        }
+        Ok(Inventory { counts })
+    }
+    /// How many beads of the colour are available, colours not in the inventory have none
+    pub fn get(&self, name: &str) -> u32 {
+        self.counts.get(name).copied().unwrap_or(0)
+    }
+    /// Names in the inventory that aren't colours of the palette, sorted, e.g. because of typos
+    pub fn unknown_colours(&self, palette: &Palette) -> Vec<&str> {
+        let mut unknown: Vec<_> = self
+            .counts
+            .keys()
+            .map(|name| &**name)
+            .filter(|&name| palette.bead_info_by_name(name).is_none())
+            .collect();
+        unknown.sort_unstable();
+        unknown
+    }
+}
@@ -0,0 +1,36 @@
+//! Converts an image into one with a given palette either as beads or pixels
+
+pub mod beads;
+pub mod error;
+mod font;
+pub mod grid;
+pub mod inventory;
+pub mod palette;
+pub mod pdf;
+pub mod pegboard;
+pub mod process;
+pub mod report;
+pub mod size;
+pub mod svg;
+pub mod symbols;
+
+pub use crate::{
+    beads::{draw_beads, BeadStyle},
+    error::{PerlurError, Result},
+    grid::{overlay_grid, GridOptions},
+    inventory::Inventory,
+    palette::{BeadInfo, BuiltinPalette, Palette, BUILTIN_PALETTES},
+    pdf::{pattern_pdf, Paper, PdfOptions},
+    pegboard::{split_into_boards, Board, BoardSize},
+    process::{
+        convert, render, scale_beads, show_pearls, BeadLook, BeadPattern, DistanceMeasure, Dither,
+        DownscaleFilter, Options,
+    },
+    report::{
+        format_counts, sorted_counts, BeadCount, BoardCounts, CountOrder, ReportFormat,
+        ShoppingItem, ShoppingList,
+    },
+    size::{BeadSize, FitMode, Length},
+    svg::{pattern_svg, BeadShape, SvgOptions},
+    symbols::{contrasting_colour, draw_symbols, Symbol, Symbols},
+};
@@ -1,29 +1,52 @@
 use std::{
-    io::{BufRead, BufReader},
-    fs::File,
+    fs,
+    io::{stdin, stdout, Cursor, Read, Write},
     path::{Path, PathBuf},
-    mem::swap,
+    process::ExitCode,
 };
 
-use clap::Parser;
-use image::Rgb;
-use process::{DistanceMeasure, DownscaleFilter};
-
-use crate::process::{create_beads, output};
-
-mod process;
+use clap::{Parser, Subcommand};
+use image::{DynamicImage, ImageError, ImageFormat, Rgb, RgbaImage};
+use perlur::{
+    convert, draw_symbols, format_counts, overlay_grid, palette::parse_colour, pattern_pdf,
+    pattern_svg, render, sorted_counts, split_into_boards, BeadCount, BeadLook, BeadShape,
+    BeadSize, BeadStyle, BoardCounts, BoardSize, CountOrder, DistanceMeasure, Dither,
+    DownscaleFilter, FitMode, GridOptions, Inventory, Length, Options, Palette, Paper, PdfOptions,
+    PerlurError, ReportFormat, Result, ShoppingList, SvgOptions, Symbols, BUILTIN_PALETTES,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// The image to convert
-    input_img: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The image to convert, `-` to read it from standard input
+    #[arg(required = true)]
+    input_img: Option<PathBuf>,
     #[arg(short, long = "out")]
-    /// The resulting file, if no value is given the input path with the extension `.perlur.png` is used
+    /// The resulting file, `-` to write it as PNG to standard output.
+    /// If no value is given the input path with the extension `.perlur.png` is used,
+    /// or standard output if the input is standard input
     output_path: Option<PathBuf>,
     /// The amount of pixels squared to read per bead
-    #[arg(short, long, default_value = "1")]
+    #[arg(short, long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(conflicts_with_all = ["width", "height"])]
     bead_density: u32,
+    #[arg(long)]
+    /// Width of the pattern in beads, or in `cm` or `mm` using the pitch of `BEAD_SIZE`.
+    /// Without a height, the height follows from the aspect ratio of the image
+    width: Option<Length>,
+    #[arg(long)]
+    /// Height of the pattern in beads, or in `cm` or `mm` using the pitch of `BEAD_SIZE`
+    height: Option<Length>,
+    #[arg(long, default_value = "midi")]
+    /// Size of the beads, to work out the number of beads for a width or height in `cm` or `mm`
+    /// and the spacing of beads in PDF charts
+    bead_size: BeadSize,
+    #[arg(long, default_value = "fit")]
+    /// How to fit the image when both width and height are given
+    fit: FitMode,
     /// Scale of output picture
     #[arg(short = 's', long)]
     output_scale: Option<u32>,
@@ -32,6 +55,14 @@ struct Args {
     /// The algorithm to calculate distance between palette colours and colours in the source image
     /// used to determine the output colour
     distance: DistanceMeasure,
+    #[arg(long, default_value = "none")]
+    /// Dithering to better represent colours in between palette colours, either by error diffusion
+    /// spreading the difference between source and bead colours onto neighbouring beads
+    /// or by ordered dithering giving a regular pattern
+    dither: Dither,
+    #[arg(long, default_value = "1", value_parser = parse_dither_strength)]
+    /// How strongly to dither, 0 being no dithering
+    dither_strength: f32,
     #[arg(long = "filter", default_value = "catmull-rom")]
     /// Method with which to downscale the image `BEAD_DENSITY` times
     downscale_filter: DownscaleFilter,
@@ -40,74 +71,412 @@ struct Args {
     #[arg(short, long)]
     mirror: bool,
 
-    #[arg(short, long, default_value = "palette.txt")]
-    /// Path to palette file formatted as lines of a colour name, a space and then the RGB hex value of the colour
+    #[arg(short, long, default_value = "builtin:hama")]
+    /// Path to palette file formatted as lines of a colour name, a space and then the RGB hex value of the colour,
+    /// or `builtin:` followed by the name of a built-in palette. Only Hama palettes are built in,
+    /// see `perlur palettes list`
     palette: PathBuf,
-    #[arg(long, default_value = "perla.png", conflicts_with("output_scale"))]
-    /// If no `OUTPUT_SCALE` is given, this image for each bead multiplying the bead colour
-    perla: PathBuf,
+    #[arg(long, value_delimiter = ',')]
+    /// Only use palette colours matching one of these comma separated names, globs (e.g. `1?_*`)
+    /// or `tag:` followed by a tag
+    only: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    /// Don't use palette colours matching any of these names, globs or tags
+    exclude: Vec<String>,
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    /// Use at most this many palette colours, chosen to give the least colour error for the image
+    max_colours: Option<u64>,
+    #[arg(short, long)]
+    /// File listing how many beads of each colour are available as lines of a colour name and a count.
+    /// Colours are then only used as much as there are beads of them, and colours not listed aren't used
+    inventory: Option<PathBuf>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "table")]
+    /// Print what to buy for the pattern using pack sizes and prices from the palette,
+    /// minus the beads in the stock or else the inventory
+    shopping_list: Option<ReportFormat>,
+    #[arg(long)]
+    /// Write the shopping list to this file instead of printing it, as a table unless
+    /// `--shopping-list` gives another format
+    shopping_list_out: Option<PathBuf>,
+    #[arg(long)]
+    /// File of beads already owned in the same format as the inventory, subtracted in the shopping list
+    /// without limiting which colours the pattern uses
+    stock: Option<PathBuf>,
+    #[arg(long)]
+    /// Format of the bead counts, if not given they are printed as lines of a name, a colon and the count
+    counts_format: Option<ReportFormat>,
+    #[arg(long = "sort", default_value = "name")]
+    /// Order of the colours in the bead counts
+    count_order: CountOrder,
+    #[arg(long)]
+    /// Write the bead counts to this file instead of printing them
+    counts_out: Option<PathBuf>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "29x29")]
+    /// Also split the pattern onto pegboards of this size in beads, given as `WIDTHxHEIGHT` or a single number
+    /// for square boards. Each board is saved as its own image next to the output with its row and column
+    /// in the file name, and its bead counts follow those of the whole pattern
+    pegboard: Option<BoardSize>,
+    #[arg(long)]
+    /// Also write a printable chart of the pattern with a symbol for each colour and a legend to this PDF file,
+    /// with a page for each pegboard if the pattern is split onto pegboards
+    pdf: Option<PathBuf>,
+    #[arg(long, default_value = "a4")]
+    /// Paper size of the PDF chart
+    paper: Paper,
+    #[arg(long)]
+    /// Also write the pattern as an SVG image to this file, with an element for each bead
+    svg: Option<PathBuf>,
+    #[arg(long, default_value = "circle")]
+    /// Shape of the beads in the SVG image
+    bead_shape: BeadShape,
+    #[arg(long)]
+    /// Write a symbol unique to the colour on each bead in the output images and the SVG image,
+    /// so colours can be told apart in greyscale. Beads must be at least 9 pixels wide in the output images
+    symbols: bool,
+    #[arg(long)]
+    /// Draw grid lines between the beads in the output images and the SVG image
+    grid: bool,
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Every how many beads the grid lines are heavier, with medium lines halfway between if it's even
+    grid_every: u32,
+    #[arg(long)]
+    /// Number the rows and columns in margins of the output images and the SVG image
+    coordinates: bool,
+    #[arg(long, conflicts_with("output_scale"))]
+    /// If no `OUTPUT_SCALE` is given, draw each bead as this image multiplied by the bead colour
+    /// instead of drawing the beads with shading
+    perla: Option<PathBuf>,
+    #[arg(long, default_value = "24", value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(conflicts_with_all = ["output_scale", "perla"])]
+    /// Width and height of each drawn bead in pixels
+    bead_pixels: u32,
+    #[arg(long, value_parser = parse_colour, conflicts_with_all = ["output_scale", "perla"])]
+    /// Colour of the pegboard to show between drawn beads, through their holes and through translucent beads,
+    /// e.g. `#f0f0f0`. Without it the background is transparent
+    background: Option<Rgb<u8>>,
+    #[arg(short = 'j', long)]
+    /// Number of threads to use, defaults to the number of CPUs
+    threads: Option<usize>,
 }
 
-fn main() {
+#[derive(Subcommand)]
+enum Command {
+    /// Work with the built-in palettes
+    #[command(subcommand)]
+    Palettes(PalettesCommand),
+}
+
+#[derive(Subcommand)]
+enum PalettesCommand {
+    /// List the built-in palettes, which are only for Hama beads
+    List,
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<()> {
     let Args {
+        command,
         input_img,
         output_path,
         bead_density,
+        width,
+        height,
+        bead_size,
+        fit,
         output_scale,
         distance,
+        dither,
+        dither_strength,
         downscale_filter,
         mirror,
         palette,
+        only,
+        exclude,
+        max_colours,
+        inventory,
+        shopping_list,
+        shopping_list_out,
+        stock,
+        counts_format,
+        count_order,
+        counts_out,
+        pegboard,
+        pdf,
+        paper,
+        svg,
+        bead_shape,
+        symbols,
+        grid,
+        grid_every,
+        coordinates,
         perla,
-    } = Args::parse();
-    let output_path = output_path.unwrap_or_else(|| input_img.with_extension("perlur.png"));
+        bead_pixels,
+        background,
+        threads,
+    } = args;
+    if let Some(command) = command {
+        return run_command(command);
+    }
+    let input_img = input_img.expect("an input image is required without a subcommand");
 
-    let palette = read_palette(&palette);
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("the global thread pool is only set up once");
+    }
+    let output_path = output_path.unwrap_or_else(|| {
+        if is_std_stream(&input_img) {
+            input_img.clone()
+        } else {
+            input_img.with_extension("perlur.png")
+        }
+    });
+    // The bead counts must not end up in the image when it's written to standard output
+    let report = |line: &str| {
+        if is_std_stream(&output_path) {
+            eprintln!("{line}")
+        } else {
+            println!("{line}")
+        }
+    };
 
-    let (frequency, mut beads) = create_beads(
-        &input_img,
-        bead_density,
-        &palette,
+    let mut palette = Palette::load(&palette)?;
+    let inventory = inventory.as_deref().map(Inventory::read).transpose()?;
+    let stock = stock.as_deref().map(Inventory::read).transpose()?;
+    // Checked before selecting colours, as the inventory may list colours left out on purpose
+    for (file, beads) in [("inventory", &inventory), ("stock", &stock)] {
+        let unknown = beads.as_ref().map(|b| b.unknown_colours(&palette));
+        if let Some(unknown) = unknown.filter(|u| !u.is_empty()) {
+            eprintln!(
+                "warning: the {file} lists colours not in the palette, which are ignored: {}",
+                unknown.join(", ")
+            );
+        }
+    }
+    if !only.is_empty() || !exclude.is_empty() {
+        palette = palette.select(&only, &exclude)?;
+    }
+
+    let options = Options {
+        pixels_pr_bead: bead_density,
+        width: width.map(|w| w.beads(bead_size)),
+        height: height.map(|h| h.beads(bead_size)),
+        fit,
         distance,
-        downscale_filter,
-    );
+        dither,
+        dither_strength,
+        filter: downscale_filter,
+        max_colours: max_colours.map(|n| n as usize),
+        inventory: inventory.as_ref(),
+    };
+    let img = open_input(&input_img)?;
+    let mut pattern = convert(&img, &palette, &options);
 
     if mirror {
-        for mut row in beads.rows_mut() {
-            loop {
-                match (row.next(), row.next_back()) {
-                    (Some(first), Some(last)) => swap(first, last),
-                    _ => break,
+        pattern.mirror();
+    }
+
+    let boards = match pegboard {
+        Some(size) => split_into_boards(&pattern, &palette, size),
+        None => Vec::new(),
+    };
+    let mut counts = sorted_counts(&pattern.frequency, &palette, count_order);
+    if inventory.is_some() {
+        for count in &mut counts {
+            count.missing = Some(pattern.shortfall.get(count.name).copied().unwrap_or(0));
+        }
+    }
+    let board_counts: Vec<_> = boards
+        .iter()
+        .map(|board| BoardCounts {
+            row: board.row + 1,
+            column: board.column + 1,
+            counts: sorted_counts(&board.frequency, &palette, count_order),
+        })
+        .collect();
+    let counts = match counts_format {
+        Some(format) => format_counts(&counts, &board_counts, format),
+        None => {
+            let mut total_pearls = 0;
+            let mut lines = String::new();
+            for BeadCount { name, count, .. } in counts {
+                total_pearls += count;
+                lines.push_str(&format!("{name}: {count}\n"));
+            }
+            lines.push_str(&format!(" Total: {total_pearls}"));
+            if !pattern.shortfall.is_empty() {
+                lines.push_str("\nMissing from inventory:");
+                for (name, missing) in &pattern.shortfall {
+                    lines.push_str(&format!("\n{name}: {missing}"));
+                }
+            }
+            for BoardCounts {
+                row,
+                column,
+                counts,
+            } in board_counts
+            {
+                lines.push_str(&format!("\nBoard at row {row}, column {column}:"));
+                for BeadCount { name, count, .. } in counts {
+                    lines.push_str(&format!("\n{name}: {count}"));
                 }
             }
+            lines
+        }
+    };
+    match counts_out {
+        Some(path) => fs::write(&path, counts + "\n")
+            .map_err(|source| PerlurError::WriteCounts { path, source })?,
+        None => report(&counts),
+    }
+    if shopping_list.is_some() || shopping_list_out.is_some() {
+        let list = ShoppingList::new(&pattern.frequency, &palette, stock.or(inventory).as_ref());
+        let list = list.format(shopping_list.unwrap_or(ReportFormat::Table));
+        match shopping_list_out {
+            Some(path) => fs::write(&path, list + "\n")
+                .map_err(|source| PerlurError::WriteShoppingList { path, source })?,
+            None => report(&list),
         }
     }
 
-    let mut total_pearls = 0;
-    for (name, pearls) in frequency {
-        total_pearls += pearls;
-        println!("{name}: {pearls}");
+    // The PDF and SVG writers give out their own symbols
+    let bead_symbols = symbols.then(|| Symbols::allocate(&pattern.frequency, &palette));
+    let grid_options = GridOptions {
+        lines: grid,
+        every: grid_every,
+        coordinates,
+    };
+    let look = match perla {
+        Some(perla) => BeadLook::Perla(perla),
+        None => BeadLook::Drawn(BeadStyle {
+            size: bead_pixels,
+            background,
+        }),
+    };
+    // Renders beads with the symbols and grid asked for, `palette_index` giving the colour of each bead
+    let render_guide =
+        |beads: &RgbaImage, palette_index: &(dyn Fn(u32, u32) -> Option<usize> + Sync)| {
+            let translucent =
+                |x, y| palette_index(x, y).is_some_and(|i| palette.bead_info(i).translucent);
+            let mut img = render(beads, output_scale, &look, translucent)?;
+            if let Some(bead_symbols) = &bead_symbols {
+                draw_symbols(&mut img, beads, bead_symbols, palette_index);
+            }
+            if grid || coordinates {
+                img = overlay_grid(&img, beads, &grid_options);
+            }
+            Ok::<_, PerlurError>(img)
+        };
+    for board in &boards {
+        let (row, column) = (board.row + 1, board.column + 1);
+        let img = render_guide(&board.beads, &|x, y| board.palette_index(x, y))?;
+        save_output(&img, &board_path(&output_path, row, column))?;
+    }
+
+    if let Some(path) = pdf {
+        let title = match input_img.file_name() {
+            Some(name) if !is_std_stream(&input_img) => name.to_string_lossy(),
+            _ => "perlur pattern".into(),
+        };
+        let options = PdfOptions { paper, bead_size };
+        let chart = pattern_pdf(&title, &pattern, &boards, &palette, &options);
+        fs::write(&path, chart).map_err(|source| PerlurError::WritePdf { path, source })?;
     }
-    println!(" Total: {total_pearls}");
 
-    output(beads, &output_path, output_scale, &perla);
+    if let Some(path) = svg {
+        let options = SvgOptions {
+            shape: bead_shape,
+            bead_size,
+            symbols,
+            grid,
+            grid_every,
+            coordinates,
+        };
+        fs::write(&path, pattern_svg(&pattern, &palette, &options))
+            .map_err(|source| PerlurError::WriteSvg { path, source })?;
+    }
+
+    let img = render_guide(&pattern.beads, &|x, y| pattern.palette_index(x, y))?;
+    save_output(&img, &output_path)
 }
 
-fn read_palette(path: &Path) -> Vec<(Box<str>, Rgb<u8>)> {
-    let mut palette = Vec::new();
-    for line in BufReader::new(File::open(path).unwrap()).lines() {
-        let line = line.unwrap();
-        let line = line.trim();
-        let (name, hex) = line.split_once(' ').unwrap();
-        let hex = u32::from_str_radix(hex, 16).unwrap();
-        palette.push((name.into(), make_rgb(hex)));
+/// Parses a dither strength, which must be a finite number that isn't negative
+fn parse_dither_strength(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(strength) if strength.is_finite() && strength >= 0. => Ok(strength),
+        _ => Err(format!("`{s}` is not a number of at least 0")),
     }
-    palette
 }
 
-fn make_rgb(rgb: u32) -> Rgb<u8> {
-    let r = (rgb >> 16) as u8;
-    let g = (rgb >> 8) as u8;
-    let b = rgb as u8;
-    Rgb([r, g, b])
+/// Path to save the board at the given row and column to, next to the output
+fn board_path(output_path: &Path, row: u32, column: u32) -> PathBuf {
+    let stem = match output_path.file_stem() {
+        Some(stem) if !is_std_stream(output_path) => stem.to_string_lossy(),
+        _ => "perlur".into(),
+    };
+    output_path.with_file_name(format!("{stem}.board-{row}-{column}.png"))
+}
+
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Palettes(PalettesCommand::List) => {
+            for builtin in BUILTIN_PALETTES {
+                println!(
+                    "builtin:{:<16} {:>3} colours  {}",
+                    builtin.name,
+                    builtin.palette().len(),
+                    builtin.description
+                );
+            }
+            eprintln!(
+                "Only Hama palettes are built in, other brands can be loaded from a palette file"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether the path is `-` meaning standard input or output
+fn is_std_stream(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn open_input(path: &Path) -> Result<DynamicImage> {
+    if is_std_stream(path) {
+        let mut buf = Vec::new();
+        stdin()
+            .read_to_end(&mut buf)
+            .map_err(ImageError::from)
+            .and_then(|_| image::load_from_memory(&buf))
+            .map_err(|source| PerlurError::ReadStdin { source })
+    } else {
+        image::open(path).map_err(|source| PerlurError::OpenImage {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+fn save_output(img: &RgbaImage, path: &Path) -> Result<()> {
+    if is_std_stream(path) {
+        let mut buf = Cursor::new(Vec::new());
+        img.write_to(&mut buf, ImageFormat::Png)
+            .and_then(|()| Ok(stdout().write_all(buf.get_ref())?))
+            .map_err(|source| PerlurError::WriteStdout { source })
+    } else {
+        img.save(path).map_err(|source| PerlurError::SaveImage {
+            path: path.to_owned(),
+            source,
+        })
+    }
 }
@@ -0,0 +1,510 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use image::Rgb;
+
+use crate::error::{PerlurError, Result};
+
+pub use self::builtin::{BuiltinPalette, BUILTIN_PALETTES};
+
+mod ase;
+mod builtin;
+mod gpl;
+mod structured;
+
+/// The bead colours available, each with a name
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    colours: Vec<(Box<str>, Rgb<u8>)>,
+    /// Information about the bead of each colour, in the same order as `colours`
+    beads: Vec<BeadInfo>,
+}
+
+/// What is known about the bead of a palette colour, only structured palettes can provide this
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BeadInfo {
+    pub brand: Option<Box<str>>,
+    /// The manufacturer's code for the colour
+    pub code: Option<Box<str>>,
+    /// A more readable name than the colour name
+    pub display_name: Option<Box<str>>,
+    pub translucent: bool,
+    pub glitter: bool,
+    pub price_per_bead: Option<f64>,
+    /// How many beads of the colour come in a pack
+    pub pack_size: Option<u32>,
+    /// Free-form tags to select colours by, e.g. `neon` or `pastel`
+    pub tags: Vec<Box<str>>,
+}
+
+impl BeadInfo {
+    /// Whether the bead has the tag, `translucent` and `glitter` being implied by the flags
+    pub fn has_tag(&self, tag: &str) -> bool {
+        (tag == "translucent" && self.translucent)
+            || (tag == "glitter" && self.glitter)
+            || self.tags.iter().any(|t| &**t == tag)
+    }
+}
+
+impl Palette {
+    pub fn new(colours: Vec<(Box<str>, Rgb<u8>)>) -> Self {
+        let beads = vec![BeadInfo::default(); colours.len()];
+        Palette { colours, beads }
+    }
+    /// Reads a palette file, either a TOML palette (`.toml`), a GIMP palette (`.gpl`),
+    /// an Adobe Swatch Exchange file (`.ase`) or otherwise a text file with a colour on each line
+    ///
+    /// A line consists of the colour name followed by whitespace and then the colour,
+    /// written as `rrggbb`, `#rrggbb`, `#rgb` or `rgb(r, g, b)`.
+    /// Names containing whitespace can be put in double quotes.
+    /// Blank lines and everything after a `#` that doesn't start a colour are ignored.
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|source| PerlurError::ReadPalette {
+            path: path.to_owned(),
+            source,
+        })?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+
+        if bytes.starts_with(ase::MAGIC) || extension.as_deref() == Some("ase") {
+            return ase::parse(path, &bytes);
+        }
+        let text = String::from_utf8(bytes).map_err(|_| PerlurError::InvalidPalette {
+            path: path.to_owned(),
+            reason: "file is neither valid UTF-8 text nor a swatch exchange file".to_owned(),
+        })?;
+        if text.starts_with(gpl::MAGIC) || extension.as_deref() == Some("gpl") {
+            return gpl::parse(path, &text);
+        }
+        if extension.as_deref() == Some("toml") {
+            return structured::parse(path, &text);
+        }
+
+        parse_text(path, &text)
+    }
+    /// Gets a built-in palette if `path` starts with `builtin:` followed by its name,
+    /// otherwise reads the palette file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        match path.to_str().and_then(|p| p.strip_prefix(builtin::PREFIX)) {
+            Some(name) => Palette::builtin(name),
+            None => Palette::read(path),
+        }
+    }
+    /// Parses a TOML palette for tests of code using bead information
+    #[cfg(test)]
+    pub(crate) fn from_toml(text: &str) -> Self {
+        structured::parse(Path::new("test.toml"), text).unwrap()
+    }
+    pub fn colours(&self) -> &[(Box<str>, Rgb<u8>)] {
+        &self.colours
+    }
+    /// Information about the bead of the colour at `index` in [`Palette::colours`]
+    pub fn bead_info(&self, index: usize) -> &BeadInfo {
+        &self.beads[index]
+    }
+    /// Information about the bead of the colour with the given name
+    pub fn bead_info_by_name(&self, name: &str) -> Option<&BeadInfo> {
+        self.colours
+            .iter()
+            .position(|(n, _)| &**n == name)
+            .map(|i| &self.beads[i])
+    }
+    pub fn len(&self) -> usize {
+        self.colours.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.colours.is_empty()
+    }
+    /// Keeps only the colours matching any of the `only` patterns, if any are given,
+    /// and then removes the colours matching any of the `exclude` patterns
+    ///
+    /// A pattern is either `tag:` followed by a tag or a glob matched against the colour name,
+    /// where `*` matches any text and `?` matches a single character.
+    /// It is an error if a pattern matches no colours or no colours are left.
+    pub fn select<S: AsRef<str>>(&self, only: &[S], exclude: &[S]) -> Result<Palette> {
+        let matches = |pattern: &str, i: usize| match pattern.strip_prefix("tag:") {
+            Some(tag) => self.beads[i].has_tag(tag),
+            None => glob_match(pattern, &self.colours[i].0),
+        };
+        for pattern in only.iter().chain(exclude).map(AsRef::as_ref) {
+            if !(0..self.len()).any(|i| matches(pattern, i)) {
+                return Err(PerlurError::UnmatchedColourPattern {
+                    pattern: pattern.into(),
+                });
+            }
+        }
+
+        let (colours, beads) = (0..self.len())
+            .filter(|&i| only.is_empty() || only.iter().any(|p| matches(p.as_ref(), i)))
+            .filter(|&i| !exclude.iter().any(|p| matches(p.as_ref(), i)))
+            .map(|i| (self.colours[i].clone(), self.beads[i].clone()))
+            .unzip();
+        let palette = Palette { colours, beads };
+
+        if palette.is_empty() {
+            return Err(PerlurError::NoColoursSelected);
+        }
+        Ok(palette)
+    }
+}
+
+/// Matches `text` against a glob where `*` matches any text and `?` matches any single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume if the text after the last `*` turns out not to match
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Collects the colours read from a palette file, making sure their names are unique
+struct PaletteBuilder<'a> {
+    path: &'a Path,
+    colours: Vec<(Box<str>, Rgb<u8>)>,
+    beads: Vec<BeadInfo>,
+    name_lines: HashMap<Box<str>, usize>,
+}
+
+impl<'a> PaletteBuilder<'a> {
+    fn new(path: &'a Path) -> Self {
+        PaletteBuilder {
+            path,
+            colours: Vec::new(),
+            beads: Vec::new(),
+            name_lines: HashMap::new(),
+        }
+    }
+    /// Adds a colour, `line` being where in the file it came from
+    fn push(&mut self, name: &str, colour: Rgb<u8>, line: usize) -> Result<()> {
+        self.push_bead(name, colour, BeadInfo::default(), line)
+    }
+    /// Adds a colour from a format where names needn't be unique, numbering repeated names
+    /// like `Untitled (2)`
+    fn push_renaming(&mut self, name: &str, colour: Rgb<u8>, line: usize) {
+        let mut unique = name.to_owned();
+        for n in 2.. {
+            if !self.name_lines.contains_key(&*unique) {
+                break;
+            }
+            unique = format!("{name} ({n})");
+        }
+        self.push(&unique, colour, line)
+            .expect("the name was checked not to be used");
+    }
+    fn push_bead(
+        &mut self,
+        name: &str,
+        colour: Rgb<u8>,
+        info: BeadInfo,
+        line: usize,
+    ) -> Result<()> {
+        if let Some(&first_line) = self.name_lines.get(name) {
+            return Err(PerlurError::DuplicateColourName {
+                path: self.path.to_owned(),
+                line,
+                first_line,
+                name: name.into(),
+            });
+        }
+        self.name_lines.insert(name.into(), line);
+        self.colours.push((name.into(), colour));
+        self.beads.push(info);
+        Ok(())
+    }
+    fn finish(self) -> Result<Palette> {
+        if self.colours.is_empty() {
+            return Err(PerlurError::EmptyPalette {
+                path: self.path.to_owned(),
+            });
+        }
+        Ok(Palette {
+            colours: self.colours,
+            beads: self.beads,
+        })
+    }
+}
+
+/// Parses the text format with a colour on each line
+fn parse_text(path: &Path, text: &str) -> Result<Palette> {
+    let mut builder = PaletteBuilder::new(path);
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let parsed = parse_line(line).map_err(|reason| PerlurError::InvalidPaletteLine {
+            path: path.to_owned(),
+            line: line_no,
+            reason,
+        })?;
+        if let Some((name, colour)) = parsed {
+            builder.push(name, colour, line_no)?;
+        }
+    }
+    builder.finish()
+}
+
+/// Splits a colour name, which may be in double quotes, from the rest of a trimmed line
+///
+/// The name may be followed by a colon, which is removed
+pub(crate) fn split_name(line: &str) -> Result<(&str, &str), String> {
+    let (name, rest) = if let Some(quoted) = line.strip_prefix('"') {
+        let (name, rest) = quoted
+            .split_once('"')
+            .ok_or("colour name is missing its closing quote")?;
+        let rest = rest.strip_prefix(':').unwrap_or(rest);
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return Err("expected whitespace after the quoted colour name".to_owned());
+        }
+        (name, rest)
+    } else {
+        let (name, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+        (name.strip_suffix(':').unwrap_or(name), rest)
+    };
+    if name.is_empty() {
+        return Err("colour name is empty".to_owned());
+    }
+    Ok((name, rest.trim_start()))
+}
+
+/// Parses a palette line, giving `None` for lines that are blank or only a comment
+fn parse_line(line: &str) -> Result<Option<(&str, Rgb<u8>)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let (name, rest) = split_name(line)?;
+    let (colour, rest) = if rest.starts_with("rgb(") {
+        let end = rest
+            .find(')')
+            .ok_or("`rgb(` is missing its closing parenthesis")?
+            + 1;
+        rest.split_at(end)
+    } else {
+        rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()))
+    };
+    if colour.is_empty() {
+        return Err("expected a colour after the colour name".to_owned());
+    }
+    let colour = parse_colour(colour)?;
+
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected `{rest}` after the colour"));
+    }
+
+    Ok(Some((name, colour)))
+}
+
+/// Parses a colour as `rrggbb`, `#rrggbb`, `#rgb` or `rgb(r, g, b)`
+pub fn parse_colour(s: &str) -> Result<Rgb<u8>, String> {
+    if let Some(components) = s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
+        let components: Vec<_> = components.split(',').map(str::trim).collect();
+        let &[r, g, b] = &components[..] else {
+            return Err(format!("`{s}` should have three components"));
+        };
+        let component = |c: &str| {
+            c.parse()
+                .map_err(|_| format!("`{c}` in `{s}` is not a number from 0 to 255"))
+        };
+        return Ok(Rgb([component(r)?, component(g)?, component(b)?]));
+    }
+
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let invalid = || format!("`{s}` is not a valid hex colour");
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    match hex.len() {
+        6 => Ok(make_rgb(value)),
+        // Shorthand where each digit is doubled
+        3 => Ok(make_rgb(
+            ((value & 0xf00) * 0x1100) | ((value & 0xf0) * 0x110) | ((value & 0xf) * 0x11),
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+/// Name for a colour that wasn't given one
+pub(crate) fn hex_name(Rgb([r, g, b]): Rgb<u8>) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn make_rgb(rgb: u32) -> Rgb<u8> {
+    let r = (rgb >> 16) as u8;
+    let g = (rgb >> 8) as u8;
+    let b = rgb as u8;
+    Rgb([r, g, b])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Palette> {
+        parse_text(Path::new("test.txt"), text)
+    }
+
+    #[test]
+    fn colour_formats() {
+        assert_eq!(parse_colour("ff8000"), Ok(Rgb([255, 128, 0])));
+        assert_eq!(parse_colour("#FF8000"), Ok(Rgb([255, 128, 0])));
+        assert_eq!(parse_colour("rgb(255, 128, 0)"), Ok(Rgb([255, 128, 0])));
+        assert_eq!(parse_colour("rgb(255,128,0)"), Ok(Rgb([255, 128, 0])));
+    }
+
+    #[test]
+    fn shorthand_hex_doubles_each_digit() {
+        assert_eq!(parse_colour("#f80"), Ok(Rgb([0xff, 0x88, 0x00])));
+        assert_eq!(parse_colour("#1a3"), Ok(Rgb([0x11, 0xaa, 0x33])));
+        assert_eq!(parse_colour("fff"), Ok(Rgb([255, 255, 255])));
+    }
+
+    #[test]
+    fn invalid_colours() {
+        for colour in [
+            "#ff80",
+            "#gg0000",
+            "+12345",
+            "rgb(1, 2)",
+            "rgb(1, 2, 256)",
+            "#",
+        ] {
+            assert!(parse_colour(colour).is_err(), "`{colour}` was accepted");
+        }
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        assert_eq!(parse_line(""), Ok(None));
+        assert_eq!(parse_line("   \t "), Ok(None));
+        assert_eq!(parse_line("# a comment"), Ok(None));
+        assert_eq!(
+            parse_line("red ff0000 # trailing comment"),
+            Ok(Some(("red", Rgb([255, 0, 0]))))
+        );
+    }
+
+    #[test]
+    fn names_and_separators() {
+        assert_eq!(
+            parse_line("red\tff0000"),
+            Ok(Some(("red", Rgb([255, 0, 0]))))
+        );
+        assert_eq!(
+            parse_line("red:  #ff0000"),
+            Ok(Some(("red", Rgb([255, 0, 0]))))
+        );
+        assert_eq!(
+            parse_line("\"light blue\" rgb(0, 128, 255)"),
+            Ok(Some(("light blue", Rgb([0, 128, 255]))))
+        );
+        assert_eq!(
+            parse_line("\"light blue\":\t#08f"),
+            Ok(Some(("light blue", Rgb([0, 0x88, 0xff]))))
+        );
+        assert!(parse_line("\"light blue ff0000").is_err());
+        assert!(parse_line("\"\" ff0000").is_err());
+        assert!(parse_line("red").is_err());
+        assert!(parse_line("red ff0000 extra").is_err());
+    }
+
+    #[test]
+    fn palette_keeps_file_order() {
+        let palette =
+            parse("# colours\nwhite fff\n\n\"dark grey\"\t404040\nblack #000000\n").unwrap();
+        let names: Vec<_> = palette.colours().iter().map(|(n, _)| &**n).collect();
+        assert_eq!(names, ["white", "dark grey", "black"]);
+        assert_eq!(palette.colours()[1].1, Rgb([0x40, 0x40, 0x40]));
+    }
+
+    #[test]
+    fn invalid_line_reports_line_number() {
+        match parse("white fff\n\nblack nothex\n") {
+            Err(PerlurError::InvalidPaletteLine { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected an invalid line, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn duplicate_names_report_both_lines() {
+        match parse("white fff\nblack 000\n# again\nwhite ffffff\n") {
+            Err(PerlurError::DuplicateColourName {
+                line,
+                first_line,
+                name,
+                ..
+            }) => {
+                assert_eq!((line, first_line, &*name), (4, 1, "white"));
+            }
+            other => panic!("expected a duplicate name, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn palette_without_colours_is_empty() {
+        assert!(matches!(
+            parse("# nothing here\n\n"),
+            Err(PerlurError::EmptyPalette { .. })
+        ));
+    }
+
+    #[test]
+    fn glob_question_mark_and_star() {
+        assert!(glob_match("1?_*", "12_green"));
+        assert!(glob_match("1?_*", "10_"));
+        assert!(!glob_match("1?_*", "1_green"));
+        assert!(!glob_match("1?_*", "123_green"));
+    }
+
+    #[test]
+    fn glob_star_alone_matches_anything() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "white"));
+    }
+
+    #[test]
+    fn glob_trailing_star() {
+        assert!(glob_match("neon*", "neon"));
+        assert!(glob_match("neon*", "neon_green"));
+        assert!(!glob_match("neon*", "pastel_neon"));
+    }
+
+    #[test]
+    fn glob_several_stars() {
+        assert!(glob_match("*_*_*", "a_b_c"));
+        assert!(glob_match("*gr*n*", "light_green_2"));
+        assert!(glob_match("**a**", "banana"));
+        assert!(!glob_match("*_*_*", "a_b"));
+    }
+
+    #[test]
+    fn glob_without_wildcards_matches_exactly() {
+        assert!(glob_match("red", "red"));
+        assert!(!glob_match("red", "dark_red"));
+        assert!(!glob_match("red", "reddish"));
+        assert!(!glob_match("", "red"));
+    }
+}
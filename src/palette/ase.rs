@@ -0,0 +1,254 @@
+//! Adobe Swatch Exchange files
+
+use std::path::Path;
+
+use image::Rgb;
+use lab::Lab;
+
+use super::{hex_name, Palette, PaletteBuilder};
+use crate::error::{PerlurError, Result};
+
+pub(super) const MAGIC: &[u8] = b"ASEF";
+
+const GROUP_START: u16 = 0xc001;
+const GROUP_END: u16 = 0xc002;
+const COLOUR_ENTRY: u16 = 0x0001;
+
+/// Parses a swatch exchange file, taking every colour swatch regardless of group
+///
+/// CMYK swatches are converted naïvely without a colour profile
+pub(super) fn parse(path: &Path, bytes: &[u8]) -> Result<Palette> {
+    parse_swatches(path, bytes).map_err(|e| match e {
+        SwatchError::Invalid(reason) => PerlurError::InvalidPalette {
+            path: path.to_owned(),
+            reason,
+        },
+        SwatchError::Palette(e) => e,
+    })
+}
+
+enum SwatchError {
+    Invalid(String),
+    Palette(PerlurError),
+}
+
+impl From<PerlurError> for SwatchError {
+    fn from(value: PerlurError) -> Self {
+        SwatchError::Palette(value)
+    }
+}
+
+impl From<&str> for SwatchError {
+    fn from(value: &str) -> Self {
+        SwatchError::Invalid(value.to_owned())
+    }
+}
+
+fn parse_swatches(path: &Path, bytes: &[u8]) -> Result<Palette, SwatchError> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != MAGIC {
+        return Err("missing `ASEF` signature".into());
+    }
+    let _version = (reader.u16()?, reader.u16()?);
+    let block_count = reader.u32()?;
+
+    let mut builder = PaletteBuilder::new(path);
+    let mut swatch_no = 0;
+    for _ in 0..block_count {
+        let block_type = reader.u16()?;
+        let length = reader.u32()? as usize;
+        let mut block = Reader(reader.take(length)?);
+
+        match block_type {
+            COLOUR_ENTRY => {
+                swatch_no += 1;
+                let name = block.utf16_string()?;
+                let model = block.take(4)?;
+                let colour = match model {
+                    b"RGB " => Rgb([block.f32()?, block.f32()?, block.f32()?].map(unit_to_u8)),
+                    b"CMYK" => {
+                        let cmy = [block.f32()?, block.f32()?, block.f32()?];
+                        let k = block.f32()?;
+                        Rgb(cmy.map(|v| unit_to_u8((1. - v) * (1. - k))))
+                    }
+                    b"LAB " => {
+                        let lab = Lab {
+                            l: block.f32()? * 100.,
+                            a: block.f32()?,
+                            b: block.f32()?,
+                        };
+                        Rgb(lab.to_rgb())
+                    }
+                    b"Gray" => Rgb([unit_to_u8(block.f32()?); 3]),
+                    _ => {
+                        return Err(SwatchError::Invalid(format!(
+                            "unknown colour model `{}`",
+                            String::from_utf8_lossy(model)
+                        )))
+                    }
+                };
+                if name.is_empty() {
+                    builder.push_renaming(&hex_name(colour), colour, swatch_no);
+                } else {
+                    builder.push_renaming(&name, colour, swatch_no);
+                }
+            }
+            // Groups only organise the swatches, so the colours are read as if they weren't there
+            GROUP_START | GROUP_END => (),
+            _ => {
+                return Err(SwatchError::Invalid(format!(
+                    "unknown block type {block_type:#06x}"
+                )))
+            }
+        }
+    }
+    Ok(builder.finish()?)
+}
+
+fn unit_to_u8(v: f32) -> u8 {
+    (v * 255.).round().clamp(0., 255.) as u8
+}
+
+/// Reads big endian values from the swatch file
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SwatchError> {
+        if self.0.len() < n {
+            return Err("file ends unexpectedly".into());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+    fn u16(&mut self) -> Result<u16, SwatchError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, SwatchError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn f32(&mut self) -> Result<f32, SwatchError> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    /// A string as its length in UTF-16 code units including a terminating zero followed by the code units
+    fn utf16_string(&mut self) -> Result<String, SwatchError> {
+        let len = self.u16()? as usize;
+        let units: Vec<u16> = (0..len).map(|_| self.u16()).collect::<Result<_, _>>()?;
+        let units = units.strip_suffix(&[0]).unwrap_or(&units);
+        String::from_utf16(units).map_err(|_| "swatch name is not valid UTF-16".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a swatch exchange file from blocks of a type and content
+    fn file(blocks: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([0, 1, 0, 0]);
+        bytes.extend((blocks.len() as u32).to_be_bytes());
+        for (block_type, content) in blocks {
+            bytes.extend(block_type.to_be_bytes());
+            bytes.extend((content.len() as u32).to_be_bytes());
+            bytes.extend(content);
+        }
+        bytes
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        let mut bytes = (units.len() as u16).to_be_bytes().to_vec();
+        bytes.extend(units.into_iter().flat_map(u16::to_be_bytes));
+        bytes
+    }
+
+    fn swatch(swatch_name: &str, model: &[u8; 4], values: &[f32]) -> (u16, Vec<u8>) {
+        let mut bytes = name(swatch_name);
+        bytes.extend(model);
+        bytes.extend(values.iter().flat_map(|v| v.to_be_bytes()));
+        // Colour type, 2 meaning a normal colour
+        bytes.extend(2u16.to_be_bytes());
+        (COLOUR_ENTRY, bytes)
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> Result<Palette> {
+        parse(Path::new("test.ase"), bytes)
+    }
+
+    #[test]
+    fn colour_models() {
+        let palette = parse_bytes(&file(&[
+            swatch("orange", b"RGB ", &[1., 0.5, 0.]),
+            swatch("cyan", b"CMYK", &[1., 0., 0., 0.]),
+            swatch("white", b"LAB ", &[1., 0., 0.]),
+            swatch("grey", b"Gray", &[0.5]),
+        ]))
+        .unwrap();
+        assert_eq!(
+            palette.colours(),
+            [
+                ("orange".into(), Rgb([255, 128, 0])),
+                ("cyan".into(), Rgb([0, 255, 255])),
+                ("white".into(), Rgb([255, 255, 255])),
+                ("grey".into(), Rgb([128, 128, 128])),
+            ]
+        );
+    }
+
+    #[test]
+    fn unnamed_swatches_are_named_by_hex_value() {
+        let palette = parse_bytes(&file(&[swatch("", b"RGB ", &[1., 0., 0.])])).unwrap();
+        assert_eq!(&*palette.colours()[0].0, "#ff0000");
+    }
+
+    #[test]
+    fn repeated_names_are_numbered() {
+        let palette = parse_bytes(&file(&[
+            swatch("red", b"RGB ", &[1., 0., 0.]),
+            swatch("red", b"RGB ", &[0.8, 0., 0.]),
+            swatch("", b"Gray", &[1.]),
+            swatch("", b"Gray", &[1.]),
+        ]))
+        .unwrap();
+        let names: Vec<_> = palette.colours().iter().map(|(n, _)| &**n).collect();
+        assert_eq!(names, ["red", "red (2)", "#ffffff", "#ffffff (2)"]);
+    }
+
+    #[test]
+    fn swatches_in_groups_are_read() {
+        let palette = parse_bytes(&file(&[
+            swatch("black", b"Gray", &[0.]),
+            (GROUP_START, name("Blues")),
+            swatch("blue", b"RGB ", &[0., 0., 1.]),
+            swatch("navy", b"RGB ", &[0., 0., 0.5]),
+            (GROUP_END, Vec::new()),
+        ]))
+        .unwrap();
+        let names: Vec<_> = palette.colours().iter().map(|(n, _)| &**n).collect();
+        assert_eq!(names, ["black", "blue", "navy"]);
+    }
+
+    #[test]
+    fn truncated_file_is_invalid() {
+        let bytes = file(&[swatch("orange", b"RGB ", &[1., 0.5, 0.])]);
+        for end in [3, 10, bytes.len() - 5] {
+            assert!(
+                matches!(
+                    parse_bytes(&bytes[..end]),
+                    Err(PerlurError::InvalidPalette { .. })
+                ),
+                "file cut at {end} bytes was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_colour_model_is_invalid() {
+        let bytes = file(&[swatch("odd", b"HSV ", &[0., 0., 0.])]);
+        assert!(matches!(
+            parse_bytes(&bytes),
+            Err(PerlurError::InvalidPalette { .. })
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+//! Palettes embedded in the binary so no palette file is needed
+
+use std::path::Path;
+
+use super::{parse_text, Palette};
+use crate::error::{PerlurError, Result};
+
+/// Prefix of a palette path that refers to a built-in palette instead of a file
+pub const PREFIX: &str = "builtin:";
+
+/// A palette embedded in the binary
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinPalette {
+    pub name: &'static str,
+    pub description: &'static str,
+    source: &'static str,
+}
+
+/// The built-in palettes, embedded from `palette.txt` and `palette2.txt`
+///
+/// Only Hama palettes are built in, other brands such as Perler and Artkal have to be loaded from a file.
+pub const BUILTIN_PALETTES: &[BuiltinPalette] = &[
+    BuiltinPalette {
+        name: "hama",
+        description: "Hama midi beads with colour names",
+        source: include_str!("../../palette.txt"),
+    },
+    BuiltinPalette {
+        name: "hama-numbered",
+        description: "Hama midi beads by colour number, including neon colours",
+        source: include_str!("../../palette2.txt"),
+    },
+];
+
+impl BuiltinPalette {
+    pub fn find(name: &str) -> Option<&'static Self> {
+        BUILTIN_PALETTES.iter().find(|p| p.name == name)
+    }
+    pub fn palette(&self) -> Palette {
+        let path = format!("{PREFIX}{}", self.name);
+        parse_text(Path::new(&path), self.source).expect("built-in palettes are valid")
+    }
+}
+
+impl Palette {
+    /// The built-in palette with the given name
+    pub fn builtin(name: &str) -> Result<Self> {
+        BuiltinPalette::find(name)
+            .map(BuiltinPalette::palette)
+            .ok_or_else(|| PerlurError::UnknownBuiltinPalette { name: name.into() })
+    }
+}
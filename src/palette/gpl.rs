@@ -0,0 +1,119 @@
+//! GIMP palette files
+
+use std::path::Path;
+
+use image::Rgb;
+
+use super::{hex_name, Palette, PaletteBuilder};
+use crate::error::{PerlurError, Result};
+
+pub(super) const MAGIC: &str = "GIMP Palette";
+
+/// Parses a GIMP palette, unnamed colours are named by their hex value
+///
+/// After the `GIMP Palette` header line come optional `Name:` and `Columns:` lines
+/// and then a colour on each line as decimal red, green and blue values followed by the name
+pub(super) fn parse(path: &Path, text: &str) -> Result<Palette> {
+    let invalid = |line, reason: String| PerlurError::InvalidPaletteLine {
+        path: path.to_owned(),
+        line,
+        reason,
+    };
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()));
+
+    match lines.next() {
+        Some((_, MAGIC)) => (),
+        _ => return Err(invalid(1, format!("expected `{MAGIC}` header"))),
+    }
+
+    let mut builder = PaletteBuilder::new(path);
+    for (line_no, line) in lines {
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        let mut rest = line;
+        let mut channels = [0u8; 3];
+        for channel in &mut channels {
+            let (value, tail) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+            *channel = value.parse().map_err(|_| {
+                invalid(
+                    line_no,
+                    format!("expected red, green and blue values from 0 to 255, found `{value}`"),
+                )
+            })?;
+            rest = tail.trim_start();
+        }
+        let colour = Rgb(channels);
+
+        if rest.is_empty() {
+            builder.push_renaming(&hex_name(colour), colour, line_no);
+        } else {
+            builder.push_renaming(rest, colour, line_no);
+        }
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_and_unnamed_colours() {
+        let text = "GIMP Palette\nName: Test\nColumns: 4\n# comment\n255 0 0 Red\n  0 128 255\n\n0 0 0\tDeep black\n";
+        let palette = parse(Path::new("test.gpl"), text).unwrap();
+        assert_eq!(
+            palette.colours(),
+            [
+                ("Red".into(), Rgb([255, 0, 0])),
+                ("#0080ff".into(), Rgb([0, 128, 255])),
+                ("Deep black".into(), Rgb([0, 0, 0])),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_names_are_numbered() {
+        let text =
+            "GIMP Palette\n0 0 0 Untitled\n255 255 255 Untitled\n9 9 9 Untitled\n1 2 3\n1 2 3\n";
+        let palette = parse(Path::new("test.gpl"), text).unwrap();
+        let names: Vec<_> = palette.colours().iter().map(|(n, _)| &**n).collect();
+        assert_eq!(
+            names,
+            [
+                "Untitled",
+                "Untitled (2)",
+                "Untitled (3)",
+                "#010203",
+                "#010203 (2)"
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_header_is_invalid() {
+        assert!(matches!(
+            parse(Path::new("test.gpl"), "255 0 0 Red\n"),
+            Err(PerlurError::InvalidPaletteLine { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn out_of_range_value_reports_line() {
+        assert!(matches!(
+            parse(
+                Path::new("test.gpl"),
+                "GIMP Palette\n255 0 0 Red\n256 0 0 Too red\n"
+            ),
+            Err(PerlurError::InvalidPaletteLine { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+//! TOML palettes with information about each bead
+//!
+//! ```toml
+//! brand = "Hama"
+//!
+//! [[bead]]
+//! name = "01_white"
+//! code = "H01"
+//! display_name = "White"
+//! colour = "#d3d4d2"
+//! price_per_bead = 0.002
+//! pack_size = 1000
+//!
+//! [[bead]]
+//! name = "61_glitter_red"
+//! colour = "rgb(200, 30, 40)"
+//! glitter = true
+//! tags = ["christmas"]
+//! ```
+//!
+//! `brand` at the top is used for beads without their own.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{parse_colour, BeadInfo, Palette, PaletteBuilder};
+use crate::error::{PerlurError, Result};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaletteFile {
+    brand: Option<Box<str>>,
+    #[serde(default)]
+    bead: Vec<Bead>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Bead {
+    name: Box<str>,
+    #[serde(alias = "color")]
+    colour: String,
+    brand: Option<Box<str>>,
+    code: Option<Box<str>>,
+    display_name: Option<Box<str>>,
+    #[serde(default)]
+    translucent: bool,
+    #[serde(default)]
+    glitter: bool,
+    price_per_bead: Option<f64>,
+    pack_size: Option<u32>,
+    #[serde(default)]
+    tags: Vec<Box<str>>,
+}
+
+pub(super) fn parse(path: &Path, text: &str) -> Result<Palette> {
+    let file: PaletteFile = toml::from_str(text).map_err(|e| PerlurError::InvalidPalette {
+        path: path.to_owned(),
+        reason: e.to_string(),
+    })?;
+
+    let mut builder = PaletteBuilder::new(path);
+    for (i, bead) in file.bead.into_iter().enumerate() {
+        let colour = parse_colour(&bead.colour).map_err(|reason| PerlurError::InvalidPalette {
+            path: path.to_owned(),
+            reason: format!("bead `{}`: {reason}", bead.name),
+        })?;
+        let info = BeadInfo {
+            brand: bead.brand.or_else(|| file.brand.clone()),
+            code: bead.code,
+            display_name: bead.display_name,
+            translucent: bead.translucent,
+            glitter: bead.glitter,
+            price_per_bead: bead.price_per_bead,
+            pack_size: bead.pack_size,
+            tags: bead.tags,
+        };
+        builder.push_bead(&bead.name, colour, info, i + 1)?;
+    }
+    builder.finish()
+}
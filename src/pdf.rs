@@ -0,0 +1,387 @@
+//! Printable pattern charts as PDF
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use clap::ValueEnum;
+use image::Rgb;
+
+use crate::{
+    grid::{is_labelled, line_weight, LineWeight},
+    palette::Palette,
+    pegboard::Board,
+    process::BeadPattern,
+    size::BeadSize,
+    symbols::{Symbol, Symbols},
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Paper {
+    /// 210 × 297 mm
+    A4,
+    /// 8.5 × 11 inches
+    Letter,
+}
+
+impl Paper {
+    /// Width and height in points
+    fn size(self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (595.28, 841.89),
+            Paper::Letter => (612., 792.),
+        }
+    }
+}
+
+/// Settings for PDF charts
+#[derive(Debug, Clone, Copy)]
+pub struct PdfOptions {
+    pub paper: Paper,
+    /// Size of the beads, charts are printed with their pitch so they can be placed under a pegboard
+    pub bead_size: BeadSize,
+}
+
+const POINTS_PER_MM: f32 = 72. / 25.4;
+const MARGIN: f32 = 36.;
+/// Room for the row and column numbers next to the grid
+const LABEL_SPACE: f32 = 16.;
+/// Height kept free below the grid for the start of the legend
+const LEGEND_SPACE: f32 = 72.;
+const LEGEND_ROW: f32 = 14.;
+const LEGEND_COLUMN: f32 = 180.;
+
+/// A grid of beads to draw on a page
+struct Chart<'a> {
+    title: String,
+    width: u32,
+    height: u32,
+    index: Box<dyn Fn(u32, u32) -> Option<usize> + 'a>,
+    frequency: &'a BTreeMap<&'a str, u32>,
+}
+
+/// Makes a PDF chart of the pattern with a page for each board, or a single chart of the whole pattern
+/// if `boards` is empty
+///
+/// Each bead is drawn in its colour with a symbol unique to the colour, along with a legend of the colours
+/// used and their counts. Charts are printed at the pitch of the bead size and only scaled down
+/// if they don't fit on the paper.
+pub fn pattern_pdf(
+    title: &str,
+    pattern: &BeadPattern,
+    boards: &[Board],
+    palette: &Palette,
+    options: &PdfOptions,
+) -> Vec<u8> {
+    let symbols = Symbols::allocate(&pattern.frequency, palette);
+    let charts: Vec<_> = if boards.is_empty() {
+        vec![Chart {
+            title: title.to_owned(),
+            width: pattern.beads.width(),
+            height: pattern.beads.height(),
+            index: Box::new(|x, y| pattern.palette_index(x, y)),
+            frequency: &pattern.frequency,
+        }]
+    } else {
+        boards
+            .iter()
+            .map(|board| Chart {
+                title: format!(
+                    "{title}, board at row {}, column {}",
+                    board.row + 1,
+                    board.column + 1
+                ),
+                width: board.beads.width(),
+                height: board.beads.height(),
+                index: Box::new(|x, y| board.palette_index(x, y)),
+                frequency: &board.frequency,
+            })
+            .collect()
+    };
+
+    let (paper_width, paper_height) = options.paper.size();
+    let mut pages = Vec::new();
+    for chart in &charts {
+        draw_chart(&mut pages, chart, palette, &symbols, options);
+    }
+    write_pdf(&pages, paper_width, paper_height)
+}
+
+/// Draws the chart and its legend onto new pages
+fn draw_chart(
+    pages: &mut Vec<String>,
+    chart: &Chart,
+    palette: &Palette,
+    symbols: &Symbols,
+    options: &PdfOptions,
+) {
+    let (paper_width, paper_height) = options.paper.size();
+    let left = MARGIN + LABEL_SPACE;
+    let top = MARGIN + 20. + LABEL_SPACE;
+    let available_width = paper_width - MARGIN - left;
+    let available_height = paper_height - MARGIN - top - LEGEND_SPACE;
+
+    let pitch = options.bead_size.pitch_mm() * POINTS_PER_MM;
+    let scale = (available_width / (chart.width as f32 * pitch))
+        .min(available_height / (chart.height as f32 * pitch))
+        .min(1.);
+    let cell = pitch * scale;
+
+    let black = Rgb([0, 0, 0]);
+    let mut page = Page::new(paper_height);
+    let title = if scale < 1. {
+        format!("{} (scaled to {:.0}%)", chart.title, scale * 100.)
+    } else {
+        chart.title.clone()
+    };
+    page.text(MARGIN, MARGIN + 12., 12., black, &title);
+
+    let colours = palette.colours();
+    for y in 0..chart.height {
+        for x in 0..chart.width {
+            let Some(i) = (chart.index)(x, y) else {
+                continue;
+            };
+            let (cell_x, cell_y) = (left + x as f32 * cell, top + y as f32 * cell);
+            page.rect(cell_x, cell_y, cell, cell, colours[i].1);
+            if let Some(symbol) = symbols.get(i) {
+                page.centred_symbol(cell_x, cell_y, cell, symbol);
+            }
+        }
+    }
+
+    let grid_width = chart.width as f32 * cell;
+    let grid_height = chart.height as f32 * cell;
+    for x in 0..=chart.width {
+        let line_x = left + x as f32 * cell;
+        page.line(line_x, top, line_x, top + grid_height, line_width(x));
+    }
+    for y in 0..=chart.height {
+        let line_y = top + y as f32 * cell;
+        page.line(left, line_y, left + grid_width, line_y, line_width(y));
+    }
+
+    for x in (1..=chart.width).filter(|&n| is_labelled(n, 10)) {
+        let label = x.to_string();
+        let label_x = left + (x as f32 - 0.5) * cell - text_width(&label, 6.) / 2.;
+        page.text(label_x, top - 4., 6., black, &label);
+    }
+    for y in (1..=chart.height).filter(|&n| is_labelled(n, 10)) {
+        let label = y.to_string();
+        let label_y = top + (y as f32 - 0.5) * cell + 2.;
+        page.text(
+            left - 3. - text_width(&label, 6.),
+            label_y,
+            6.,
+            black,
+            &label,
+        );
+    }
+
+    let mut legend: Vec<_> = chart
+        .frequency
+        .iter()
+        .filter_map(|(&name, &count)| {
+            let i = colours.iter().position(|(n, _)| &**n == name)?;
+            Some((i, name, count))
+        })
+        .collect();
+    legend.sort_by_key(|&(i, _, _)| i);
+
+    let columns = ((paper_width - 2. * MARGIN) / LEGEND_COLUMN)
+        .floor()
+        .max(1.) as usize;
+    let mut y = top + grid_height + 28.;
+    page.text(MARGIN, y, 10., black, "Colours");
+    y += 8.;
+    for row in legend.chunks(columns) {
+        if y + LEGEND_ROW > paper_height - MARGIN {
+            pages.push(page.content);
+            page = Page::new(paper_height);
+            page.text(
+                MARGIN,
+                MARGIN + 12.,
+                12.,
+                black,
+                &format!("{} (continued)", chart.title),
+            );
+            y = MARGIN + 28.;
+        }
+        for (column, &(i, name, count)) in row.iter().enumerate() {
+            let x = MARGIN + column as f32 * LEGEND_COLUMN;
+            let colour = colours[i].1;
+            page.rect(x, y, 10., 10., colour);
+            page.outline(x, y, 10., 10.);
+            if let Some(symbol) = symbols.get(i) {
+                page.centred_symbol(x, y, 10., symbol);
+            }
+            page.text(x + 16., y + 8., 8., black, name);
+            let count = count.to_string();
+            let count_x = x + LEGEND_COLUMN - 12. - text_width(&count, 8.);
+            page.text(count_x, y + 8., 8., black, &count);
+        }
+        y += LEGEND_ROW;
+    }
+    let total: u32 = chart.frequency.values().sum();
+    page.text(MARGIN, y + 10., 8., black, &format!("Total: {total} beads"));
+    pages.push(page.content);
+}
+
+/// Lines are heavier every 5 and 10 beads to make counting easier
+fn line_width(n: u32) -> f32 {
+    match line_weight(n, 10) {
+        LineWeight::Thin => 0.2,
+        LineWeight::Medium => 0.6,
+        LineWeight::Heavy => 1.2,
+    }
+}
+
+/// Content of a page being drawn, with positions given from the top left in points
+struct Page {
+    height: f32,
+    content: String,
+}
+
+impl Page {
+    fn new(height: f32) -> Self {
+        Page {
+            height,
+            content: String::new(),
+        }
+    }
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, Rgb([r, g, b]): Rgb<u8>) {
+        let _ = writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} rg {x:.2} {:.2} {width:.2} {height:.2} re f",
+            r as f32 / 255.,
+            g as f32 / 255.,
+            b as f32 / 255.,
+            self.height - y - height,
+        );
+    }
+    fn outline(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let _ = writeln!(
+            self.content,
+            "0.2 w 0.2 G {x:.2} {:.2} {width:.2} {height:.2} re S",
+            self.height - y - height,
+        );
+    }
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32) {
+        let _ = writeln!(
+            self.content,
+            "{width} w 0.2 G {x1:.2} {:.2} m {x2:.2} {:.2} l S",
+            self.height - y1,
+            self.height - y2,
+        );
+    }
+    /// Writes text with its baseline at `y`
+    fn text(&mut self, x: f32, y: f32, size: f32, Rgb([r, g, b]): Rgb<u8>, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /F1 {size:.2} Tf {:.3} {:.3} {:.3} rg {x:.2} {:.2} Td ({}) Tj ET",
+            r as f32 / 255.,
+            g as f32 / 255.,
+            b as f32 / 255.,
+            self.height - y,
+            pdf_string(text),
+        );
+    }
+    /// Writes a symbol in the middle of a square cell
+    fn centred_symbol(&mut self, x: f32, y: f32, cell: f32, symbol: &Symbol) {
+        let size = if symbol.text.chars().count() > 1 {
+            cell * 0.45
+        } else {
+            cell * 0.6
+        }
+        .min(cell * 0.9 / text_width(&symbol.text, 1.));
+        let x = x + (cell - text_width(&symbol.text, size)) / 2.;
+        // Capital letters are about 0.72 of the font size high
+        let y = y + (cell + 0.72 * size) / 2.;
+        self.text(x, y, size, symbol.colour, &symbol.text);
+    }
+}
+
+/// Glyph widths of Helvetica for the printable ASCII characters, in thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Width of text set in Helvetica at the font size
+fn text_width(text: &str, size: f32) -> f32 {
+    let thousandths: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => HELVETICA_WIDTHS[c as usize - 32] as u32,
+            _ => 556,
+        })
+        .sum();
+    thousandths as f32 * size / 1000.
+}
+
+/// Escapes text for a PDF string in WinAnsi encoding, replacing characters it can't represent with `?`
+fn pdf_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+/// Puts the page contents together into a PDF document using the built-in Helvetica font
+fn write_pdf(pages: &[String], width: f32, height: f32) -> Vec<u8> {
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|i| format!("{} 0 R", 4 + 2 * i))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_owned(),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width:.2} {height:.2}] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            5 + 2 * i
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ));
+    }
+
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = out.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    out.extend_from_slice(trailer.as_bytes());
+    out
+}
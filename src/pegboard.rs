@@ -0,0 +1,121 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use image::{
+    imageops::{crop_imm, replace},
+    Rgba, RgbaImage,
+};
+
+use crate::{palette::Palette, process::BeadPattern};
+
+/// Size of a pegboard in beads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for BoardSize {
+    type Err = String;
+
+    /// Parses `WIDTHxHEIGHT` or a single number for square boards
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| match n.trim().parse() {
+            Ok(0) | Err(_) => Err(format!("`{n}` is not a positive number of beads")),
+            Ok(n) => Ok(n),
+        };
+        match s.split_once(['x', 'X']) {
+            Some((width, height)) => Ok(BoardSize {
+                width: parse(width)?,
+                height: parse(height)?,
+            }),
+            None => {
+                let side = parse(s)?;
+                Ok(BoardSize {
+                    width: side,
+                    height: side,
+                })
+            }
+        }
+    }
+}
+
+impl Display for BoardSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// The part of a pattern placed on one pegboard
+#[derive(Debug, Clone)]
+pub struct Board<'a> {
+    /// Row of the board from the top, starting at 0
+    pub row: u32,
+    /// Column of the board from the left, starting at 0
+    pub column: u32,
+    /// The beads on the board, padded with empty positions where the pattern doesn't reach
+    pub beads: RgbaImage,
+    /// How many beads of each palette colour are on the board, by name
+    pub frequency: BTreeMap<&'a str, u32>,
+    /// Index into the palette of the colour of each bead, row by row
+    indices: Vec<Option<usize>>,
+}
+
+impl Board<'_> {
+    /// Index into the palette of the colour of the bead at the position, `None` if no bead is placed there
+    ///
+    /// # Panics
+    ///
+    /// If the position is outside the board
+    pub fn palette_index(&self, x: u32, y: u32) -> Option<usize> {
+        assert!(x < self.beads.width() && y < self.beads.height());
+        self.indices[(y * self.beads.width() + x) as usize]
+    }
+}
+
+/// Splits the pattern into boards of the given size, row by row from the top left
+///
+/// The boards in the last row and column are padded so all boards have the same size
+pub fn split_into_boards<'a>(
+    pattern: &BeadPattern<'a>,
+    palette: &'a Palette,
+    size: BoardSize,
+) -> Vec<Board<'a>> {
+    let (width, height) = pattern.beads.dimensions();
+    let columns = width.div_ceil(size.width);
+    let rows = height.div_ceil(size.height);
+
+    let mut boards = Vec::with_capacity((rows * columns) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x0, y0) = (column * size.width, row * size.height);
+            let board_width = size.width.min(width - x0);
+            let board_height = size.height.min(height - y0);
+
+            let mut beads =
+                RgbaImage::from_pixel(size.width, size.height, Rgba([255, 255, 255, 0]));
+            let part = crop_imm(&pattern.beads, x0, y0, board_width, board_height).to_image();
+            replace(&mut beads, &part, 0, 0);
+
+            let mut frequency = BTreeMap::new();
+            let mut indices = vec![None; (size.width * size.height) as usize];
+            for y in 0..board_height {
+                for x in 0..board_width {
+                    let index = pattern.palette_index(x0 + x, y0 + y);
+                    if let Some(i) = index {
+                        *frequency.entry(&*palette.colours()[i].0).or_insert(0) += 1;
+                    }
+                    indices[(y * size.width + x) as usize] = index;
+                }
+            }
+
+            boards.push(Board {
+                row,
+                column,
+                beads,
+                frequency,
+                indices,
+            });
+        }
+    }
+    boards
+}
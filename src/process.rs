@@ -1,17 +1,101 @@
-use std::{array, collections::BTreeMap, path::Path};
+use std::{
+    array,
+    collections::{BTreeMap, HashMap},
+    mem::swap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
 
 use clap::ValueEnum;
 use image::{
     imageops::{resize, FilterType},
-    GenericImageView, Pixel, Rgb, Rgba, RgbaImage,
+    DynamicImage, GenericImageView, Pixel, Rgb, Rgba, RgbaImage,
 };
 use lab::Lab;
+use rayon::prelude::*;
+
+use crate::{
+    beads::{draw_beads, BeadStyle},
+    error::{PerlurError, Result},
+    inventory::Inventory,
+    palette::Palette,
+    size::{scaling, FitMode, Scaling},
+};
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum DistanceMeasure {
     Rgb,
+    /// RGB distance weighted by the mean red value to approximate perception
+    Redmean,
     Lab,
+    Oklab,
+    Cie94,
+    Ciede2000,
 }
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Dither {
+    /// Every bead gets its nearest palette colour
+    None,
+    /// Floyd–Steinberg error diffusion
+    FloydSteinberg,
+    /// Atkinson error diffusion, only diffuses 3/4 of the error giving more contrast
+    Atkinson,
+    /// Ordered dithering with a 2x2 Bayer matrix
+    Bayer2,
+    /// Ordered dithering with a 4x4 Bayer matrix
+    Bayer4,
+    /// Ordered dithering with an 8x8 Bayer matrix
+    Bayer8,
+}
+
+impl Dither {
+    /// Offsets (dx, dy) and weights of neighbours receiving quantisation error,
+    /// given for scanning left to right
+    fn kernel(self) -> &'static [(i32, u32, f32)] {
+        match self {
+            Dither::None | Dither::Bayer2 | Dither::Bayer4 | Dither::Bayer8 => &[],
+            Dither::FloydSteinberg => &[
+                (1, 0, 7. / 16.),
+                (-1, 1, 3. / 16.),
+                (0, 1, 5. / 16.),
+                (1, 1, 1. / 16.),
+            ],
+            Dither::Atkinson => &[
+                (1, 0, 1. / 8.),
+                (2, 0, 1. / 8.),
+                (-1, 1, 1. / 8.),
+                (0, 1, 1. / 8.),
+                (1, 1, 1. / 8.),
+                (0, 2, 1. / 8.),
+            ],
+        }
+    }
+    /// Base two logarithm of the side length of the Bayer matrix for ordered dithering
+    fn bayer_order(self) -> Option<u32> {
+        match self {
+            Dither::Bayer2 => Some(1),
+            Dither::Bayer4 => Some(2),
+            Dither::Bayer8 => Some(3),
+            _ => None,
+        }
+    }
+}
+
+/// Threshold from a Bayer matrix of side length `2^order` at the given position in the range -0.5..0.5
+fn bayer_threshold(order: u32, x: u32, y: u32) -> f32 {
+    let mut index = 0;
+    for bit in 0..order {
+        index = (index << 2) | (((x ^ y) >> bit & 1) << 1) | (y >> bit & 1);
+    }
+    (index as f32 + 0.5) / (1 << (2 * order)) as f32 - 0.5
+}
+
+/// How far ordered dithering at strength 1 may push a colour channel in either direction
+const ORDERED_DITHER_SPREAD: f32 = 64.;
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum DownscaleFilter {
     Nearest,
@@ -33,84 +117,735 @@ impl From<DownscaleFilter> for FilterType {
     }
 }
 
-pub fn create_beads<'a, 'b>(
-    img_path: &'b Path,
-    pixels_pr_bead: u32,
-    palette: &'a [(Box<str>, Rgb<u8>)],
-    distance: DistanceMeasure,
-    filter: DownscaleFilter,
-) -> (BTreeMap<&'a str, u32>, RgbaImage) {
-    let img = image::open(img_path).unwrap();
-    let width = img.width() / pixels_pr_bead;
-    let height = img.height() / pixels_pr_bead;
+/// Settings for converting an image into beads
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    /// The amount of pixels squared to read per bead, unless `width` or `height` is given
+    pub pixels_pr_bead: u32,
+    /// Width of the pattern in beads, if only one of `width` and `height` is given
+    /// the other follows from the aspect ratio of the image
+    pub width: Option<u32>,
+    /// Height of the pattern in beads
+    pub height: Option<u32>,
+    /// How to fit the image when both `width` and `height` are given
+    pub fit: FitMode,
+    pub distance: DistanceMeasure,
+    pub dither: Dither,
+    /// How strongly to dither, 0 being no dithering, must be finite and not negative
+    pub dither_strength: f32,
+    pub filter: DownscaleFilter,
+    /// Only use at most this many palette colours, chosen to fit the image best
+    pub max_colours: Option<usize>,
+    /// Beads available of each colour, when given colours are only used as much as there is stock for
+    pub inventory: Option<&'a Inventory>,
+}
 
-    let mut img = img.resize_exact(width, height, filter.into()).into_rgba8();
-    let mut frequency = BTreeMap::new();
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Options {
+            pixels_pr_bead: 1,
+            width: None,
+            height: None,
+            fit: FitMode::Fit,
+            distance: DistanceMeasure::Lab,
+            dither: Dither::None,
+            dither_strength: 1.,
+            filter: DownscaleFilter::CatmullRom,
+            max_colours: None,
+            inventory: None,
+        }
+    }
+}
+
+/// The beads an image was converted into
+#[derive(Debug, Clone)]
+pub struct BeadPattern<'a> {
+    /// How many beads of each palette colour are used, by name
+    pub frequency: BTreeMap<&'a str, u32>,
+    /// How many more beads of each colour are used than there are in the inventory
+    pub shortfall: BTreeMap<&'a str, u32>,
+    /// One pixel per bead, transparent where no bead is placed
+    pub beads: RgbaImage,
+    /// Index into the palette of the colour of each bead, row by row
+    indices: Vec<u16>,
+}
 
-    let distance = match distance {
-        DistanceMeasure::Lab => distance_lab,
-        DistanceMeasure::Rgb => distance_rgb,
+impl BeadPattern<'_> {
+    /// Mirrors the pattern horizontally, e.g. for ironing it from the back
+    pub fn mirror(&mut self) {
+        for mut row in self.beads.rows_mut() {
+            while let (Some(first), Some(last)) = (row.next(), row.next_back()) {
+                swap(first, last);
+            }
+        }
+        for row in self.indices.chunks_mut(self.beads.width().max(1) as usize) {
+            row.reverse();
+        }
+    }
+    /// Index into the palette of the colour of the bead at the position, `None` if no bead is placed there
+    ///
+    /// # Panics
+    ///
+    /// If the position is outside the pattern
+    pub fn palette_index(&self, x: u32, y: u32) -> Option<usize> {
+        assert!(x < self.beads.width() && y < self.beads.height());
+        let i = self.indices[(y * self.beads.width() + x) as usize];
+        (i != NO_BEAD).then_some(i as usize)
+    }
+}
+
+/// Converts an image into beads of the colours in `palette`
+///
+/// # Panics
+///
+/// If `palette` is empty, `options.pixels_pr_bead` is 0 without a width or height,
+/// the width or height is 0, or `options.dither_strength` is negative or not finite
+pub fn convert<'a>(img: &DynamicImage, palette: &'a Palette, options: &Options) -> BeadPattern<'a> {
+    let &Options {
+        pixels_pr_bead,
+        width,
+        height,
+        fit,
+        distance,
+        dither,
+        dither_strength,
+        filter,
+        max_colours,
+        inventory,
+    } = options;
+    assert!(!palette.is_empty(), "palette has no colours");
+    let palette = palette.colours();
+
+    assert!(
+        width != Some(0) && height != Some(0),
+        "pattern has no beads"
+    );
+    assert!(
+        dither_strength.is_finite() && dither_strength >= 0.,
+        "dither strength must be finite and not negative"
+    );
+
+    let mut img = match scaling(img.dimensions(), width, height, fit) {
+        Some(Scaling {
+            width,
+            height,
+            crop: (x, y, crop_width, crop_height),
+        }) => img
+            .resize_exact(width, height, filter.into())
+            .crop_imm(x, y, crop_width, crop_height)
+            .into_rgba8(),
+        None => {
+            let width = img.width() / pixels_pr_bead;
+            let height = img.height() / pixels_pr_bead;
+            img.resize_exact(width, height, filter.into()).into_rgba8()
+        }
+    };
+    let (width, height) = img.dimensions();
+
+    let candidates = match max_colours {
+        Some(max_colours) if max_colours < palette.len() => {
+            best_colours(&img, palette, distance, max_colours)
+        }
+        _ => (0..palette.len()).collect(),
     };
+    let mut matcher = ColourMatcher::new(
+        palette,
+        &candidates,
+        distance,
+        width as usize * height as usize,
+    );
 
-    img.pixels_mut().for_each(|p| {
+    let kernel = dither.kernel();
+    let bayer_order = dither.bayer_order();
+    // Returns the colour to look up in the palette, or `None` if the bead should be left empty
+    let target_colour = |p: &mut Rgba<u8>, x: u32, y: u32| {
         let Rgba([r, g, b, a]) = *p;
         if a < 128 {
             *p = Rgba([255, 255, 255, 0]);
-        } else {
-            let target = Rgb([r, g, b]);
-
-            let mut colour = target;
-            let mut best_dist = f32::INFINITY;
-            let mut chosen_name = "";
-            for &(ref name, candidate_colour) in palette.iter() {
-                let dist = distance(candidate_colour, target);
-                if dist < best_dist {
-                    best_dist = dist;
-                    colour = candidate_colour;
-                    chosen_name = &**name;
+            return None;
+        }
+        let mut target = Rgb([r, g, b]);
+        if let Some(order) = bayer_order {
+            let offset = bayer_threshold(order, x, y) * ORDERED_DITHER_SPREAD * dither_strength;
+            target = Rgb(target
+                .0
+                .map(|c| (c as f32 + offset).round().clamp(0., 255.) as u8));
+        }
+        Some(target)
+    };
+
+    // The original colours are needed to decide which beads to move when running out of stock
+    let source = inventory.map(|_| img.clone());
+    // Index into the palette of the colour of each bead
+    let mut assigned = vec![NO_BEAD; width as usize * height as usize];
+
+    if kernel.is_empty() {
+        // Without error diffusion every bead is independent, so rows can be done in parallel
+        let row_len = width as usize * 4;
+        img.par_chunks_mut(row_len.max(1))
+            .zip(assigned.par_chunks_mut((width as usize).max(1)))
+            .enumerate()
+            .for_each_init(
+                || matcher.clone(),
+                |matcher, (y, (row, assigned))| {
+                    for (x, (p, assigned)) in row.chunks_exact_mut(4).zip(assigned).enumerate() {
+                        let p = Rgba::from_slice_mut(p);
+                        if let Some(target) = target_colour(p, x as u32, y as u32) {
+                            let i = matcher.nearest(target);
+                            *assigned = i as u16;
+                            *p = palette[i].1.to_rgba();
+                        }
+                    }
+                },
+            );
+    } else {
+        // Accumulated quantisation error per bead
+        let mut error = vec![[0f32; 3]; width as usize * height as usize];
+
+        for y in 0..height {
+            // Serpentine scanning: every other row is scanned right to left
+            // so the error doesn't keep getting pushed in the same direction
+            let reverse = y % 2 == 1;
+            for i in 0..width {
+                let x = if reverse { width - 1 - i } else { i };
+                let p = img.get_pixel_mut(x, y);
+
+                let Some(target) = target_colour(p, x, y) else {
+                    continue;
+                };
+                let e = error[(y * width + x) as usize];
+                let target = Rgb(array::from_fn(|c| {
+                    (target.0[c] as f32 + e[c]).round().clamp(0., 255.) as u8
+                }));
+
+                let i = matcher.nearest(target);
+                let colour = palette[i].1;
+                assigned[(y * width + x) as usize] = i as u16;
+                *p = colour.to_rgba();
+
+                let quant_error: [f32; 3] =
+                    array::from_fn(|c| target.0[c] as f32 - colour.0[c] as f32);
+                for &(dx, dy, weight) in kernel {
+                    let dx = if reverse { -dx } else { dx };
+                    let (nx, ny) = (x as i32 + dx, y + dy);
+                    if nx < 0 || nx >= width as i32 || ny >= height {
+                        continue;
+                    }
+                    let e = &mut error[(ny * width) as usize + nx as usize];
+                    for c in 0..3 {
+                        e[c] += quant_error[c] * weight * dither_strength;
+                    }
                 }
             }
+        }
+    }
 
-            *frequency.entry(chosen_name).or_insert(0u32) += 1;
-            *p = colour.to_rgba();
+    let mut shortfall = BTreeMap::new();
+    if let (Some(inventory), Some(source)) = (inventory, source) {
+        let stock: Vec<u32> = palette
+            .iter()
+            .map(|(name, _)| inventory.get(name))
+            .collect();
+        let used = respect_stock(
+            &mut assigned,
+            &source,
+            palette,
+            &candidates,
+            distance,
+            &stock,
+        );
+
+        for (bead, &i) in img.pixels_mut().zip(&assigned) {
+            if i != NO_BEAD {
+                *bead = palette[i as usize].1.to_rgba();
+            }
         }
-    });
+        for (i, (name, _)) in palette.iter().enumerate() {
+            if used[i] > stock[i] {
+                *shortfall.entry(&**name).or_insert(0) += used[i] - stock[i];
+            }
+        }
+    }
+
+    let mut frequency = BTreeMap::new();
+    for &i in &assigned {
+        if i != NO_BEAD {
+            *frequency.entry(&*palette[i as usize].0).or_insert(0) += 1;
+        }
+    }
+
+    BeadPattern {
+        frequency,
+        shortfall,
+        beads: img,
+        indices: assigned,
+    }
+}
+
+/// Marks a bead position where no bead is placed
+const NO_BEAD: u16 = u16::MAX;
+
+/// Moves beads of colours used more than there is `stock` for to their next best colour
+/// that there are still beads of, moving the beads where it makes the least difference first
+///
+/// Returns how many beads of each palette colour are used afterwards
+fn respect_stock(
+    assigned: &mut [u16],
+    source: &RgbaImage,
+    palette: &[(Box<str>, Rgb<u8>)],
+    candidates: &[usize],
+    measure: DistanceMeasure,
+    stock: &[u32],
+) -> Vec<u32> {
+    let colour_space = measure.colour_space();
+    let distance = measure.distance();
+    let palette_coords: Vec<_> = palette.iter().map(|&(_, c)| colour_space(c)).collect();
+
+    let mut used = vec![0u32; palette.len()];
+    for &i in &*assigned {
+        if i != NO_BEAD {
+            used[i as usize] += 1;
+        }
+    }
+
+    loop {
+        let mut moved_any = false;
+        for &colour in candidates {
+            let Some(excess) = used[colour].checked_sub(stock[colour]).filter(|&e| e > 0) else {
+                continue;
+            };
+            // How much worse each bead of the colour would get by moving to the best colour left
+            let mut moves: Vec<(f32, usize, usize)> = assigned
+                .par_iter()
+                .zip(source.par_pixels())
+                .enumerate()
+                .filter(|&(_, (&i, _))| i as usize == colour)
+                .filter_map(|(bead, (_, &Rgba([r, g, b, _])))| {
+                    let target = colour_space(Rgb([r, g, b]));
+                    let current = distance(&palette_coords[colour], &target);
+                    candidates
+                        .iter()
+                        .filter(|&&c| c != colour && used[c] < stock[c])
+                        .map(|&c| (distance(&palette_coords[c], &target) - current, bead, c))
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                })
+                .collect();
+            moves.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut moved = 0;
+            for (_, bead, alternative) in moves {
+                if moved == excess {
+                    break;
+                }
+                // Earlier moves may have used up the alternative, the bead can be moved in the next round
+                if used[alternative] >= stock[alternative] {
+                    continue;
+                }
+                assigned[bead] = alternative as u16;
+                used[alternative] += 1;
+                used[colour] -= 1;
+                moved += 1;
+                moved_any = true;
+            }
+        }
+        if !moved_any {
+            break used;
+        }
+    }
+}
+
+/// Colour coordinates in the space a distance measure works in
+type Coords = [f32; 3];
+
+impl DistanceMeasure {
+    fn colour_space(self) -> fn(Rgb<u8>) -> Coords {
+        match self {
+            DistanceMeasure::Rgb | DistanceMeasure::Redmean => rgb_coords,
+            DistanceMeasure::Oklab => oklab_from_rgb,
+            DistanceMeasure::Lab | DistanceMeasure::Cie94 | DistanceMeasure::Ciede2000 => {
+                lab_coords
+            }
+        }
+    }
+    /// The distance between a palette colour and a source colour, both in the measure's colour space
+    fn distance(self) -> fn(&Coords, &Coords) -> f32 {
+        match self {
+            DistanceMeasure::Rgb | DistanceMeasure::Lab | DistanceMeasure::Oklab => {
+                distance_squared
+            }
+            DistanceMeasure::Redmean => distance_redmean,
+            DistanceMeasure::Cie94 => distance_cie94,
+            DistanceMeasure::Ciede2000 => distance_ciede2000,
+        }
+    }
+}
+
+/// Above this many beads, a full table over all 24-bit colours is used
+/// instead of a hash map to remember already matched colours
+const LOOKUP_TABLE_THRESHOLD: usize = 1 << 20;
+const UNMATCHED: u16 = u16::MAX;
+
+/// Chooses the `n` palette colours giving the least total distance to the colours of the image
+///
+/// Starting from the whole palette, the colour whose removal adds the least distance
+/// is removed until only `n` colours are left.
+/// Returns the indices of the chosen colours in `palette`.
+fn best_colours(
+    img: &RgbaImage,
+    palette: &[(Box<str>, Rgb<u8>)],
+    measure: DistanceMeasure,
+    n: usize,
+) -> Vec<usize> {
+    let mut histogram = HashMap::new();
+    for &Rgba([r, g, b, a]) in img.pixels() {
+        if a >= 128 {
+            *histogram.entry(Rgb([r, g, b])).or_insert(0u32) += 1;
+        }
+    }
+
+    let colour_space = measure.colour_space();
+    let distance = measure.distance();
+    let palette_coords: Vec<_> = palette.iter().map(|&(_, c)| colour_space(c)).collect();
+    // Distances from every image colour to every palette colour along with how often the colour occurs
+    let distances: Vec<(f64, Vec<f32>)> = histogram
+        .into_par_iter()
+        .map(|(colour, count)| {
+            let coords = colour_space(colour);
+            let distances = palette_coords
+                .iter()
+                .map(|p| distance(p, &coords))
+                .collect();
+            (count as f64, distances)
+        })
+        .collect();
+
+    let mut chosen: Vec<usize> = (0..palette.len()).collect();
+    while chosen.len() > n.max(1) {
+        // How much the total distance would grow by removing each chosen colour
+        let removal_cost = distances
+            .par_iter()
+            .fold(
+                || vec![0f64; chosen.len()],
+                |mut cost, (count, distances)| {
+                    let (mut best, mut best_dist, mut second_dist) =
+                        (0, f32::INFINITY, f32::INFINITY);
+                    for (i, &p) in chosen.iter().enumerate() {
+                        let dist = distances[p];
+                        if dist < best_dist {
+                            (second_dist, best_dist, best) = (best_dist, dist, i);
+                        } else if dist < second_dist {
+                            second_dist = dist;
+                        }
+                    }
+                    cost[best] += count * (second_dist - best_dist) as f64;
+                    cost
+                },
+            )
+            .reduce(
+                || vec![0f64; chosen.len()],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+        let (cheapest, _) = removal_cost
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("more than one colour is chosen");
+        chosen.remove(cheapest);
+    }
+    chosen
+}
 
-    (frequency, img)
+/// Finds the nearest palette colour, remembering results for colours already seen
+///
+/// Clones share the lookup table if one is used, but get their own hash map
+#[derive(Clone)]
+struct ColourMatcher {
+    /// Index in the palette and coordinates of each colour that may be chosen
+    palette: Arc<[(usize, Coords)]>,
+    colour_space: fn(Rgb<u8>) -> Coords,
+    distance: fn(&Coords, &Coords) -> f32,
+    cache: MatchCache,
 }
 
-fn distance_rgb(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
-    a.0.into_iter()
-        .zip(b.0.into_iter())
+#[derive(Clone)]
+enum MatchCache {
+    Map(HashMap<Rgb<u8>, u16>),
+    Table(Arc<[AtomicU16]>),
+}
+
+impl ColourMatcher {
+    /// Matches against the palette colours at the indices in `candidates`
+    fn new(
+        palette: &[(Box<str>, Rgb<u8>)],
+        candidates: &[usize],
+        measure: DistanceMeasure,
+        beads: usize,
+    ) -> Self {
+        assert!(palette.len() < UNMATCHED as usize, "palette is too large");
+        let colour_space = measure.colour_space();
+        let cache = if beads > LOOKUP_TABLE_THRESHOLD {
+            MatchCache::Table((0..1 << 24).map(|_| AtomicU16::new(UNMATCHED)).collect())
+        } else {
+            MatchCache::Map(HashMap::new())
+        };
+
+        ColourMatcher {
+            palette: candidates
+                .iter()
+                .map(|&i| (i, colour_space(palette[i].1)))
+                .collect(),
+            colour_space,
+            distance: measure.distance(),
+            cache,
+        }
+    }
+    /// Index of the palette colour nearest to `target`
+    fn nearest(&mut self, target: Rgb<u8>) -> usize {
+        let Rgb([r, g, b]) = target;
+        let key = (r as usize) << 16 | (g as usize) << 8 | b as usize;
+
+        let cached = match &self.cache {
+            MatchCache::Map(map) => map.get(&target).copied(),
+            MatchCache::Table(table) => {
+                Some(table[key].load(Ordering::Relaxed)).filter(|&i| i != UNMATCHED)
+            }
+        };
+        if let Some(i) = cached {
+            return i as usize;
+        }
+
+        let best = self.search(target);
+        match &mut self.cache {
+            MatchCache::Map(map) => {
+                map.insert(target, best as u16);
+            }
+            // Other threads may race to fill in the same entry, but they will all find the same colour
+            MatchCache::Table(table) => table[key].store(best as u16, Ordering::Relaxed),
+        }
+        best
+    }
+    fn search(&self, target: Rgb<u8>) -> usize {
+        let target = (self.colour_space)(target);
+        let mut best_dist = f32::INFINITY;
+        let mut best = 0;
+        for &(i, ref candidate) in self.palette.iter() {
+            let dist = (self.distance)(candidate, &target);
+            if dist < best_dist {
+                best_dist = dist;
+                best = i;
+            }
+        }
+        best
+    }
+}
+
+fn rgb_coords(rgb: Rgb<u8>) -> Coords {
+    rgb.0.map(|c| c as f32)
+}
+fn lab_coords(rgb: Rgb<u8>) -> Coords {
+    let Lab { l, a, b } = Lab::from_rgb(&rgb.0);
+    [l, a, b]
+}
+
+fn distance_squared(a: &Coords, b: &Coords) -> f32 {
+    a.iter()
+        .zip(b)
         .map(|(a, b)| {
-            let d = a as f32 - b as f32;
+            let d = a - b;
             d * d
         })
         .sum()
 }
-fn distance_lab(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
-    let a = Lab::from_rgb(&a.0);
-    let b = Lab::from_rgb(&b.0);
+fn distance_redmean(a: &Coords, b: &Coords) -> f32 {
+    let [r1, g1, b1] = *a;
+    let [r2, g2, b2] = *b;
+    let r_mean = (r1 + r2) / 2.;
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+
+    (2. + r_mean / 256.) * dr * dr + 4. * dg * dg + (2. + (255. - r_mean) / 256.) * db * db
+}
+fn distance_cie94(a: &Coords, b: &Coords) -> f32 {
+    let [l, a1, b1] = *a;
+    let a = Lab { l, a: a1, b: b1 };
+    let [l, a2, b2] = *b;
+    let b = Lab { l, a: a2, b: b2 };
+
+    // The source colour `b` is used as the reference colour
+    delta_e_cie94(&b, &a)
+}
+fn distance_ciede2000(a: &Coords, b: &Coords) -> f32 {
+    let [l, a1, b1] = *a;
+    let a = Lab { l, a: a1, b: b1 };
+    let [l, a2, b2] = *b;
+    let b = Lab { l, a: a2, b: b2 };
+
+    delta_e_ciede2000(&a, &b)
+}
+
+/// Converts an sRGB colour to Oklab coordinates `[L, a, b]`
+fn oklab_from_rgb(rgb: Rgb<u8>) -> Coords {
+    let [r, g, b] = rgb.0.map(|c| {
+        let c = c as f32 / 255.;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
 
-    a.squared_distance(&b)
+/// CIE94 colour difference with graphic arts weights, `reference` decides the chroma weighting
+fn delta_e_cie94(reference: &Lab, sample: &Lab) -> f32 {
+    const K1: f32 = 0.045;
+    const K2: f32 = 0.015;
+
+    let c1 = reference.a.hypot(reference.b);
+    let c2 = sample.a.hypot(sample.b);
+
+    let dl = reference.l - sample.l;
+    let dc = c1 - c2;
+    let da = reference.a - sample.a;
+    let db = reference.b - sample.b;
+    // ΔH² can come out slightly negative from rounding
+    let dh2 = (da * da + db * db - dc * dc).max(0.);
+
+    let sc = 1. + K1 * c1;
+    let sh = 1. + K2 * c1;
+
+    (dl * dl + (dc / sc).powi(2) + dh2 / (sh * sh)).sqrt()
 }
 
-pub fn output(beads: RgbaImage, out_path: &Path, output_scale: Option<u32>, perla: &Path) {
-    let Some(output_scale) = output_scale else {
-        return show_pearls(beads, out_path, perla);
+/// CIEDE2000 colour difference with all parametric weights set to 1
+///
+/// Follows the formulation in Sharma, Wu and Dalal (2005)
+fn delta_e_ciede2000(lab1: &Lab, lab2: &Lab) -> f32 {
+    let (l1, a1, b1) = (lab1.l as f64, lab1.a as f64, lab1.b as f64);
+    let (l2, a2, b2) = (lab2.l as f64, lab2.a as f64, lab2.b as f64);
+    const POW25_7: f64 = 6_103_515_625.;
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1. - (c_bar7 / (c_bar7 + POW25_7)).sqrt());
+
+    let a1 = (1. + g) * a1;
+    let a2 = (1. + g) * a2;
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let hue = |a: f64, b: f64| {
+        if a == 0. && b == 0. {
+            0.
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.)
+        }
+    };
+    let h1 = hue(a1, b1);
+    let h2 = hue(a2, b2);
+    let achromatic = c1 * c2 == 0.;
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = if achromatic {
+        0.
+    } else if (h2 - h1).abs() <= 180. {
+        h2 - h1
+    } else if h2 - h1 > 180. {
+        h2 - h1 - 360.
+    } else {
+        h2 - h1 + 360.
+    };
+    let dh = 2. * (c1 * c2).sqrt() * (dh / 2.).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.;
+    let c_bar = (c1 + c2) / 2.;
+    let h_bar = if achromatic {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180. {
+        (h1 + h2) / 2.
+    } else if h1 + h2 < 360. {
+        (h1 + h2 + 360.) / 2.
+    } else {
+        (h1 + h2 - 360.) / 2.
     };
 
-    let img = resize(
-        &beads,
+    let cos_deg = |d: f64| d.to_radians().cos();
+    let t = 1. - 0.17 * cos_deg(h_bar - 30.)
+        + 0.24 * cos_deg(2. * h_bar)
+        + 0.32 * cos_deg(3. * h_bar + 6.)
+        - 0.20 * cos_deg(4. * h_bar - 63.);
+    let d_theta = 30. * (-((h_bar - 275.) / 25.).powi(2)).exp();
+    let c_bar7 = c_bar.powi(7);
+    let rc = 2. * (c_bar7 / (c_bar7 + POW25_7)).sqrt();
+    let l_bar50 = (l_bar - 50.).powi(2);
+    let sl = 1. + 0.015 * l_bar50 / (20. + l_bar50).sqrt();
+    let sc = 1. + 0.045 * c_bar;
+    let sh = 1. + 0.015 * c_bar * t;
+    let rt = -(2. * d_theta).to_radians().sin() * rc;
+
+    let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+    (l * l + c * c + h * h + rt * c * h).sqrt() as f32
+}
+
+/// How beads look when they aren't just scaled up
+#[derive(Debug, Clone)]
+pub enum BeadLook {
+    /// Each bead is the image multiplied by the bead colour
+    Perla(PathBuf),
+    /// Beads are drawn with shading, needing no image
+    Drawn(BeadStyle),
+}
+
+impl Default for BeadLook {
+    fn default() -> Self {
+        BeadLook::Drawn(BeadStyle::default())
+    }
+}
+
+/// Renders the beads either scaled up by `output_scale` or drawn as beads looking like `look`,
+/// `translucent` telling whether the bead at a position lets light through
+pub fn render(
+    beads: &RgbaImage,
+    output_scale: Option<u32>,
+    look: &BeadLook,
+    translucent: impl Fn(u32, u32) -> bool + Sync,
+) -> Result<RgbaImage> {
+    Ok(match (output_scale, look) {
+        (Some(output_scale), _) => scale_beads(beads, output_scale),
+        (None, BeadLook::Perla(perla)) => {
+            let perla = image::open(perla).map_err(|source| PerlurError::OpenPerla {
+                path: perla.to_owned(),
+                source,
+            })?;
+            show_pearls(beads, &perla)
+        }
+        (None, BeadLook::Drawn(style)) => draw_beads(beads, style, translucent),
+    })
+}
+
+/// Scales the beads up so each bead becomes `output_scale` squared pixels
+pub fn scale_beads(beads: &RgbaImage, output_scale: u32) -> RgbaImage {
+    resize(
+        beads,
         beads.width() * output_scale,
         beads.height() * output_scale,
         FilterType::Nearest,
-    );
-    img.save(out_path).unwrap();
+    )
 }
 
-fn show_pearls(beads: RgbaImage, out_path: &Path, perla: &Path) {
-    let perla = image::open(perla).unwrap();
+/// Draws each bead as the `perla` image multiplied by the bead colour
+pub fn show_pearls(beads: &RgbaImage, perla: &DynamicImage) -> RgbaImage {
     let (pw, ph) = perla.dimensions();
 
     RgbaImage::from_par_fn(beads.width() * pw, beads.height() * ph, |x, y| {
@@ -122,8 +857,6 @@ fn show_pearls(beads: RgbaImage, out_path: &Path, perla: &Path) {
 
         mul_rgba(c, pc)
     })
-    .save(out_path)
-    .unwrap();
 }
 
 fn mul_rgba(a: Rgba<u8>, b: Rgba<u8>) -> Rgba<u8> {
@@ -131,3 +864,115 @@ fn mul_rgba(a: Rgba<u8>, b: Rgba<u8>) -> Rgba<u8> {
         ((a.0[i] as u16 * b.0[i] as u16) / 255) as u8
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lab(l: f32, a: f32, b: f32) -> Lab {
+        Lab { l, a, b }
+    }
+
+    /// Reference pairs and differences from Sharma, Wu and Dalal (2005), table 1
+    const CIEDE2000_PAIRS: &[([f32; 3], [f32; 3], f32)] = &[
+        ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+        ([50., 3.1571, -77.2803], [50., 0., -82.7485], 2.8615),
+        // Achromatic, one colour has no hue
+        ([50., 0., 0.], [50., -1., 2.], 2.3669),
+        ([50., -1., 2.], [50., 0., 0.], 2.3669),
+        // Hue angles on either side of 0°, the mean hue wraps around
+        ([50., 2.49, -0.001], [50., -2.49, 0.0011], 7.2195),
+        ([50., 2.49, -0.001], [50., -2.49, 0.0012], 7.2195),
+        ([50., -0.001, 2.49], [50., 0.0011, -2.49], 4.7461),
+        ([50., 2.5, 0.], [50., 0., -2.5], 4.3065),
+        ([50., 2.5, 0.], [73., 25., -18.], 27.1492),
+        ([50., 2.5, 0.], [61., -5., 29.], 22.8977),
+        ([50., 2.5, 0.], [50., 3.1736, 0.5854], 1.),
+    ];
+
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        for &([l1, a1, b1], [l2, a2, b2], expected) in CIEDE2000_PAIRS {
+            let difference = delta_e_ciede2000(&lab(l1, a1, b1), &lab(l2, a2, b2));
+            assert!(
+                (difference - expected).abs() < 5e-4,
+                "({l1}, {a1}, {b1}) and ({l2}, {a2}, {b2}) gave {difference}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn ciede2000_is_symmetric() {
+        for &([l1, a1, b1], [l2, a2, b2], _) in CIEDE2000_PAIRS {
+            let (x, y) = (lab(l1, a1, b1), lab(l2, a2, b2));
+            assert!((delta_e_ciede2000(&x, &y) - delta_e_ciede2000(&y, &x)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn cie94_graphic_arts_reference() {
+        let reference = lab(50., 2.6772, -79.7751);
+        let sample = lab(50., 0., -82.7485);
+        assert!((delta_e_cie94(&reference, &sample) - 1.3950).abs() < 5e-4);
+    }
+
+    #[test]
+    fn cie94_of_identical_colours_is_zero() {
+        let colour = lab(61., -5., 29.);
+        assert_eq!(delta_e_cie94(&colour, &colour), 0.);
+    }
+
+    fn grey(v: u8) -> Rgb<u8> {
+        Rgb([v, v, v])
+    }
+
+    /// Black, dark grey and white
+    fn greys() -> Vec<(Box<str>, Rgb<u8>)> {
+        vec![
+            ("black".into(), grey(0)),
+            ("grey".into(), grey(60)),
+            ("white".into(), grey(255)),
+        ]
+    }
+
+    /// Beads from black to dark grey
+    fn dark_source() -> RgbaImage {
+        RgbaImage::from_fn(4, 1, |x, _| grey([0, 10, 40, 25][x as usize]).to_rgba())
+    }
+
+    #[test]
+    fn stock_moves_the_cheapest_beads_first() {
+        let mut assigned = vec![0; 4];
+        let used = respect_stock(
+            &mut assigned,
+            &dark_source(),
+            &greys(),
+            &[0, 1, 2],
+            DistanceMeasure::Rgb,
+            &[2, 1, 10],
+        );
+        // The lightest bead takes the only grey bead, so the next lightest has to become white
+        // in the next round, as grey ran out after it was picked as its alternative
+        assert_eq!(assigned, [0, 0, 1, 2]);
+        assert_eq!(used, [2, 1, 1]);
+    }
+
+    #[test]
+    fn missing_stock_is_reported() {
+        let img = DynamicImage::ImageRgba8(dark_source());
+        let palette = Palette::new(greys());
+        let inventory = Inventory::new([("black".into(), 1), ("grey".into(), 1)].into());
+        let pattern = convert(
+            &img,
+            &palette,
+            &Options {
+                distance: DistanceMeasure::Rgb,
+                inventory: Some(&inventory),
+                ..Options::default()
+            },
+        );
+        assert_eq!(pattern.frequency, [("black", 3), ("grey", 1)].into());
+        assert_eq!(pattern.shortfall, [("black", 2)].into());
+        assert_eq!(pattern.palette_index(2, 0), Some(1));
+    }
+}
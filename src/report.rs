@@ -0,0 +1,466 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{inventory::Inventory, palette::Palette};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// Aligned columns for reading
+    Table,
+    Csv,
+    Json,
+}
+
+/// Order of the colours in bead counts
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CountOrder {
+    /// Most used colours first
+    Count,
+    Name,
+    /// The order the colours are in the palette
+    PaletteOrder,
+}
+
+/// Beads used of a colour
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BeadCount<'a> {
+    pub name: &'a str,
+    pub count: u32,
+    /// Beads of the colour used beyond those in the inventory, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing: Option<u32>,
+}
+
+/// Bead counts of a pattern in the given order
+pub fn sorted_counts<'a>(
+    frequency: &BTreeMap<&'a str, u32>,
+    palette: &Palette,
+    order: CountOrder,
+) -> Vec<BeadCount<'a>> {
+    let mut counts: Vec<_> = frequency
+        .iter()
+        .map(|(&name, &count)| BeadCount {
+            name,
+            count,
+            missing: None,
+        })
+        .collect();
+    match order {
+        // Already sorted by name as they come from a `BTreeMap`
+        CountOrder::Name => (),
+        // Sorting is stable, so colours with the same count stay sorted by name
+        CountOrder::Count => counts.sort_by_key(|c| Reverse(c.count)),
+        CountOrder::PaletteOrder => counts.sort_by_key(|c| {
+            palette
+                .colours()
+                .iter()
+                .position(|(name, _)| &**name == c.name)
+        }),
+    }
+    counts
+}
+
+/// Bead counts of one of the pegboards a pattern is split onto
+#[derive(Debug, Clone, Serialize)]
+pub struct BoardCounts<'a> {
+    /// Row of the board from the top, starting at 1
+    pub row: u32,
+    /// Column of the board from the left, starting at 1
+    pub column: u32,
+    pub counts: Vec<BeadCount<'a>>,
+}
+
+/// Formats bead counts along with their total, followed by the counts of each pegboard
+///
+/// Beads missing from the inventory are given in a column of their own if any counts have them.
+/// In CSV the rows of the boards come after those of the whole pattern with the row and column
+/// of the board in the first columns, which are empty for the whole pattern.
+pub fn format_counts(counts: &[BeadCount], boards: &[BoardCounts], format: ReportFormat) -> String {
+    let total = |counts: &[BeadCount]| counts.iter().map(|c| c.count).sum::<u32>();
+    let with_missing = counts
+        .iter()
+        .chain(boards.iter().flat_map(|b| &b.counts))
+        .any(|c| c.missing.is_some());
+    let header: &[&str] = if with_missing {
+        &["name", "count", "missing"]
+    } else {
+        &["name", "count"]
+    };
+    let rows = |counts: &[BeadCount]| {
+        counts
+            .iter()
+            .map(|c| {
+                let mut row = vec![c.name.to_owned(), c.count.to_string()];
+                if with_missing {
+                    row.push(optional(c.missing));
+                }
+                row
+            })
+            .collect::<Vec<_>>()
+    };
+
+    match format {
+        ReportFormat::Json => {
+            #[derive(Serialize)]
+            struct Counts<'a, 'b> {
+                counts: &'b [BeadCount<'a>],
+                total: u32,
+                #[serde(skip_serializing_if = "<[_]>::is_empty")]
+                boards: Vec<Board<'a, 'b>>,
+            }
+            #[derive(Serialize)]
+            struct Board<'a, 'b> {
+                row: u32,
+                column: u32,
+                counts: &'b [BeadCount<'a>],
+                total: u32,
+            }
+            let boards = boards
+                .iter()
+                .map(|b| Board {
+                    row: b.row,
+                    column: b.column,
+                    counts: &b.counts,
+                    total: total(&b.counts),
+                })
+                .collect();
+            serde_json::to_string_pretty(&Counts {
+                counts,
+                total: total(counts),
+                boards,
+            })
+            .expect("bead counts can always be serialised")
+        }
+        ReportFormat::Csv if boards.is_empty() => csv(header, rows(counts).into_iter()),
+        ReportFormat::Csv => {
+            let whole = rows(counts).into_iter().map(|mut row| {
+                row.splice(0..0, [String::new(), String::new()]);
+                row
+            });
+            let boards = boards.iter().flat_map(|b| {
+                rows(&b.counts).into_iter().map(|mut row| {
+                    row.splice(0..0, [b.row.to_string(), b.column.to_string()]);
+                    row
+                })
+            });
+            let header: Vec<_> = ["board_row", "board_column"]
+                .iter()
+                .chain(header)
+                .copied()
+                .collect();
+            csv(&header, whole.chain(boards))
+        }
+        ReportFormat::Table => {
+            let with_total = |counts: &[BeadCount]| {
+                let mut total_row = vec!["Total".to_owned(), total(counts).to_string()];
+                if with_missing {
+                    total_row.push(optional(
+                        counts.iter().map(|c| c.missing).sum::<Option<u32>>(),
+                    ));
+                }
+                table(header, rows(counts).into_iter().chain([total_row]))
+            };
+            let mut out = with_total(counts);
+            for board in boards {
+                out.push_str(&format!(
+                    "\n\nBoard at row {}, column {}:\n{}",
+                    board.row,
+                    board.column,
+                    with_total(&board.counts)
+                ));
+            }
+            out
+        }
+    }
+}
+
+/// What to buy of a colour to make a pattern
+#[derive(Debug, Clone, Serialize)]
+pub struct ShoppingItem<'a> {
+    pub name: &'a str,
+    /// Beads used in the pattern
+    pub needed: u32,
+    /// Beads already in the inventory
+    pub in_stock: u32,
+    pub to_buy: u32,
+    pub pack_size: Option<u32>,
+    /// Packs to buy to get at least `to_buy` beads, if the pack size is known
+    pub packs: Option<u32>,
+    pub price_per_bead: Option<f64>,
+    /// Price of the packs or otherwise of the single beads, if the price is known
+    pub cost: Option<f64>,
+}
+
+/// The beads to buy to make a pattern and what they cost
+#[derive(Debug, Clone, Serialize)]
+pub struct ShoppingList<'a> {
+    pub items: Vec<ShoppingItem<'a>>,
+    /// Cost of the items with a known price
+    pub total_cost: f64,
+    /// Whether some colours to buy have no price, so the total cost is too low
+    pub missing_prices: bool,
+}
+
+impl<'a> ShoppingList<'a> {
+    /// Makes a shopping list for the bead `frequency` of a pattern,
+    /// using the pack sizes and prices from the palette and subtracting the beads in the inventory
+    pub fn new(
+        frequency: &BTreeMap<&'a str, u32>,
+        palette: &Palette,
+        inventory: Option<&Inventory>,
+    ) -> Self {
+        let mut total_cost = 0.;
+        let mut missing_prices = false;
+
+        let items = frequency
+            .iter()
+            .map(|(&name, &needed)| {
+                let info = palette.bead_info_by_name(name);
+                let pack_size = info.and_then(|i| i.pack_size).filter(|&s| s > 0);
+                let price_per_bead = info.and_then(|i| i.price_per_bead);
+
+                let in_stock = inventory.map_or(0, |inv| inv.get(name));
+                let to_buy = needed.saturating_sub(in_stock);
+                let packs = pack_size.map(|size| to_buy.div_ceil(size));
+                let cost = price_per_bead.map(|price| {
+                    let beads = packs.zip(pack_size).map_or(to_buy, |(n, size)| n * size);
+                    beads as f64 * price
+                });
+
+                match cost {
+                    Some(cost) => total_cost += cost,
+                    None => missing_prices |= to_buy > 0,
+                }
+                ShoppingItem {
+                    name,
+                    needed,
+                    in_stock,
+                    to_buy,
+                    pack_size,
+                    packs,
+                    price_per_bead,
+                    cost,
+                }
+            })
+            .collect();
+
+        ShoppingList {
+            items,
+            total_cost,
+            missing_prices,
+        }
+    }
+    pub fn format(&self, format: ReportFormat) -> String {
+        let header = [
+            "name",
+            "needed",
+            "in_stock",
+            "to_buy",
+            "pack_size",
+            "packs",
+            "price",
+            "cost",
+        ];
+        let rows = self.items.iter().map(|item| {
+            vec![
+                item.name.to_owned(),
+                item.needed.to_string(),
+                item.in_stock.to_string(),
+                item.to_buy.to_string(),
+                optional(item.pack_size),
+                optional(item.packs),
+                optional(item.price_per_bead),
+                item.cost.map(|c| format!("{c:.2}")).unwrap_or_default(),
+            ]
+        });
+
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_string_pretty(self).expect("shopping lists can always be serialised")
+            }
+            ReportFormat::Csv => csv(&header, rows),
+            ReportFormat::Table => {
+                let mut table = table(&header, rows);
+                table.push_str(&format!("\nTotal cost: {:.2}", self.total_cost));
+                if self.missing_prices {
+                    table.push_str(" (some colours have no price)");
+                }
+                table
+            }
+        }
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Formats rows as CSV with a header line, quoting fields where needed
+pub(crate) fn csv(header: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let field = |s: &str| {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    };
+    let mut out = header.join(",");
+    for row in rows {
+        out.push('\n');
+        let row: Vec<_> = row.iter().map(|s| field(s)).collect();
+        out.push_str(&row.join(","));
+    }
+    out
+}
+
+/// Formats rows as left aligned columns with a header line
+pub(crate) fn table(header: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let rows: Vec<_> = rows.collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut out = String::new();
+    let header: Vec<_> = header.iter().map(|&h| h.to_owned()).collect();
+    for row in [&header].into_iter().chain(&rows) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let line: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts<'a>(counts: &[(&'a str, u32)]) -> Vec<BeadCount<'a>> {
+        counts
+            .iter()
+            .map(|&(name, count)| BeadCount {
+                name,
+                count,
+                missing: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn board_counts_follow_the_format() {
+        let whole = counts(&[("black", 3), ("white", 1)]);
+        let boards = [
+            BoardCounts {
+                row: 1,
+                column: 1,
+                counts: counts(&[("black", 2)]),
+            },
+            BoardCounts {
+                row: 1,
+                column: 2,
+                counts: counts(&[("black", 1), ("white", 1)]),
+            },
+        ];
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_counts(&whole, &boards, ReportFormat::Json)).unwrap();
+        assert_eq!(json["total"], 4);
+        assert_eq!(json["boards"][1]["column"], 2);
+        assert_eq!(json["boards"][1]["total"], 2);
+
+        assert_eq!(
+            format_counts(&whole, &boards, ReportFormat::Csv),
+            "board_row,board_column,name,count\n\
+             ,,black,3\n,,white,1\n1,1,black,2\n1,2,black,1\n1,2,white,1"
+        );
+        assert_eq!(
+            format_counts(&whole, &[], ReportFormat::Csv),
+            "name,count\nblack,3\nwhite,1"
+        );
+    }
+
+    #[test]
+    fn shortfall_gets_a_column() {
+        let mut whole = counts(&[("black", 3), ("white", 1)]);
+        whole[0].missing = Some(2);
+        whole[1].missing = Some(0);
+
+        assert_eq!(
+            format_counts(&whole, &[], ReportFormat::Csv),
+            "name,count,missing\nblack,3,2\nwhite,1,0"
+        );
+        assert!(format_counts(&whole, &[], ReportFormat::Table).ends_with("Total  4      2"));
+        let json: serde_json::Value =
+            serde_json::from_str(&format_counts(&whole, &[], ReportFormat::Json)).unwrap();
+        assert_eq!(json["counts"][0]["missing"], 2);
+    }
+
+    const PRICED_PALETTE: &str = r##"
+        [[bead]]
+        name = "red"
+        colour = "#ff0000"
+        price_per_bead = 0.01
+        pack_size = 100
+
+        [[bead]]
+        name = "blue"
+        colour = "#0000ff"
+        price_per_bead = 0.02
+
+        [[bead]]
+        name = "green"
+        colour = "#00ff00"
+        pack_size = 1000
+    "##;
+
+    #[test]
+    fn stock_is_subtracted_and_whole_packs_bought() {
+        let palette = Palette::from_toml(PRICED_PALETTE);
+        let frequency = [("red", 250), ("blue", 40)].into();
+        let inventory = Inventory::new([("red".into(), 100), ("blue".into(), 15)].into());
+        let list = ShoppingList::new(&frequency, &palette, Some(&inventory));
+
+        let blue = &list.items[0];
+        assert_eq!((blue.in_stock, blue.to_buy, blue.packs), (15, 25, None));
+        assert_eq!(blue.cost, Some(25. * 0.02));
+        let red = &list.items[1];
+        assert_eq!((red.needed, red.in_stock, red.to_buy), (250, 100, 150));
+        assert_eq!(red.packs, Some(2));
+        // Both packs are paid for, not just the beads needed
+        assert_eq!(red.cost, Some(200. * 0.01));
+        assert!((list.total_cost - 2.5).abs() < 1e-9);
+        assert!(!list.missing_prices);
+    }
+
+    #[test]
+    fn colours_without_price_are_flagged() {
+        let palette = Palette::from_toml(PRICED_PALETTE);
+        let list = ShoppingList::new(&[("green", 30), ("red", 50)].into(), &palette, None);
+
+        let green = &list.items[0];
+        assert_eq!((green.to_buy, green.packs, green.cost), (30, Some(1), None));
+        assert!(list.missing_prices);
+        assert!((list.total_cost - 1.).abs() < 1e-9);
+        assert!(list
+            .format(ReportFormat::Table)
+            .ends_with("Total cost: 1.00 (some colours have no price)"));
+
+        // Nothing to buy of the colour without price, so the total is complete
+        let inventory = Inventory::new([("green".into(), 30)].into());
+        let list = ShoppingList::new(&[("green", 30)].into(), &palette, Some(&inventory));
+        assert!(!list.missing_prices);
+    }
+}
@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+/// Bead sizes with their pitch on a pegboard
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum BeadSize {
+    /// 2.5 mm
+    Mini,
+    /// 5 mm
+    Midi,
+    /// 10 mm
+    Maxi,
+}
+
+impl BeadSize {
+    /// Distance between the centres of neighbouring beads in millimetres
+    pub fn pitch_mm(self) -> f32 {
+        match self {
+            BeadSize::Mini => 2.5,
+            BeadSize::Midi => 5.,
+            BeadSize::Maxi => 10.,
+        }
+    }
+}
+
+/// A length of a pattern, either in beads or physical units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Beads(u32),
+    Millimetres(f32),
+}
+
+impl Length {
+    /// The length in beads of the given size, at least one bead
+    pub fn beads(self, bead_size: BeadSize) -> u32 {
+        match self {
+            Length::Beads(beads) => beads,
+            Length::Millimetres(mm) => ((mm / bead_size.pitch_mm()).round() as u32).max(1),
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    /// Parses a number of beads, or a number followed by `cm` or `mm`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let physical = |n: &str, mm_per_unit: f32| match n.trim().parse::<f32>() {
+            Ok(n) if n > 0. && n.is_finite() => Ok(Length::Millimetres(n * mm_per_unit)),
+            _ => Err(format!("`{s}` is not a positive length")),
+        };
+        if let Some(cm) = s.strip_suffix("cm") {
+            physical(cm, 10.)
+        } else if let Some(mm) = s.strip_suffix("mm") {
+            physical(mm, 1.)
+        } else {
+            match s.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "`{s}` is not a positive number of beads or a length in `cm` or `mm`"
+                )),
+                Ok(beads) => Ok(Length::Beads(beads)),
+            }
+        }
+    }
+}
+
+/// How to make an image fit when both width and height of the pattern are given
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FitMode {
+    /// Keep the aspect ratio and make the pattern as large as possible within the size
+    Fit,
+    /// Keep the aspect ratio and cover the whole size, cropping what sticks out
+    Fill,
+    /// Stretch the image to exactly the size
+    Stretch,
+}
+
+/// The size to scale an image to and the centred part of it to keep, as `(x, y, width, height)`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Scaling {
+    pub width: u32,
+    pub height: u32,
+    pub crop: (u32, u32, u32, u32),
+}
+
+/// Works out how to scale an image of `image_width` by `image_height` pixels
+/// to the requested width and height in beads, `None` if neither is given
+pub(crate) fn scaling(
+    (image_width, image_height): (u32, u32),
+    width: Option<u32>,
+    height: Option<u32>,
+    fit: FitMode,
+) -> Option<Scaling> {
+    let aspect = image_width as f64 / image_height.max(1) as f64;
+    let by_width = |w: u32| ((w as f64 / aspect).round() as u32).max(1);
+    let by_height = |h: u32| ((h as f64 * aspect).round() as u32).max(1);
+
+    let (scaled_width, scaled_height, target_width, target_height) = match (width, height) {
+        (None, None) => return None,
+        (Some(w), None) => (w, by_width(w), w, by_width(w)),
+        (None, Some(h)) => (by_height(h), h, by_height(h), h),
+        (Some(w), Some(h)) => match fit {
+            FitMode::Stretch => (w, h, w, h),
+            FitMode::Fit if by_width(w) <= h => (w, by_width(w), w, by_width(w)),
+            FitMode::Fit => (by_height(h), h, by_height(h), h),
+            FitMode::Fill if by_width(w) >= h => (w, by_width(w), w, h),
+            FitMode::Fill => (by_height(h), h, w, h),
+        },
+    };
+
+    Some(Scaling {
+        width: scaled_width,
+        height: scaled_height,
+        crop: (
+            (scaled_width - target_width) / 2,
+            (scaled_height - target_height) / 2,
+            target_width,
+            target_height,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image twice as wide as it is high
+    const IMAGE: (u32, u32) = (200, 100);
+
+    fn scaled(width: u32, height: u32, crop: (u32, u32, u32, u32)) -> Option<Scaling> {
+        Some(Scaling {
+            width,
+            height,
+            crop,
+        })
+    }
+
+    #[test]
+    fn no_size_keeps_bead_density() {
+        assert_eq!(scaling(IMAGE, None, None, FitMode::Fit), None);
+    }
+
+    #[test]
+    fn one_side_follows_aspect_ratio() {
+        assert_eq!(
+            scaling(IMAGE, Some(40), None, FitMode::Fill),
+            scaled(40, 20, (0, 0, 40, 20))
+        );
+        assert_eq!(
+            scaling(IMAGE, None, Some(15), FitMode::Stretch),
+            scaled(30, 15, (0, 0, 30, 15))
+        );
+    }
+
+    #[test]
+    fn fit_stays_within_both_sides() {
+        assert_eq!(
+            scaling(IMAGE, Some(40), Some(40), FitMode::Fit),
+            scaled(40, 20, (0, 0, 40, 20))
+        );
+        assert_eq!(
+            scaling(IMAGE, Some(100), Some(10), FitMode::Fit),
+            scaled(20, 10, (0, 0, 20, 10))
+        );
+    }
+
+    #[test]
+    fn fill_crops_the_centre() {
+        assert_eq!(
+            scaling(IMAGE, Some(40), Some(40), FitMode::Fill),
+            scaled(80, 40, (20, 0, 40, 40))
+        );
+        assert_eq!(
+            scaling(IMAGE, Some(30), Some(5), FitMode::Fill),
+            scaled(30, 15, (0, 5, 30, 5))
+        );
+    }
+
+    #[test]
+    fn stretch_uses_exact_size() {
+        assert_eq!(
+            scaling(IMAGE, Some(40), Some(40), FitMode::Stretch),
+            scaled(40, 40, (0, 0, 40, 40))
+        );
+    }
+
+    #[test]
+    fn lengths_in_beads_and_physical_units() {
+        assert_eq!("29".parse(), Ok(Length::Beads(29)));
+        assert_eq!("12cm".parse(), Ok(Length::Millimetres(120.)));
+        assert_eq!("25 mm".parse(), Ok(Length::Millimetres(25.)));
+        assert!("0".parse::<Length>().is_err());
+        assert!("-2cm".parse::<Length>().is_err());
+        assert!("3in".parse::<Length>().is_err());
+
+        assert_eq!(Length::Millimetres(120.).beads(BeadSize::Midi), 24);
+        assert_eq!(Length::Millimetres(120.).beads(BeadSize::Mini), 48);
+        assert_eq!(Length::Millimetres(1.).beads(BeadSize::Maxi), 1);
+    }
+}
@@ -0,0 +1,211 @@
+//! Patterns as scalable vector graphics
+
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+use crate::{
+    grid::{is_labelled, line_weight, LineWeight},
+    palette::{hex_name, Palette},
+    process::BeadPattern,
+    size::BeadSize,
+    symbols::Symbols,
+};
+
+/// How beads are drawn in vector output
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum BeadShape {
+    Circle,
+    /// A square with rounded corners
+    Square,
+}
+
+/// Settings for SVG output
+#[derive(Debug, Clone, Copy)]
+pub struct SvgOptions {
+    pub shape: BeadShape,
+    /// Size of the beads, the drawing is sized to their pitch when printed
+    pub bead_size: BeadSize,
+    /// Write a symbol unique to the colour on each bead
+    pub symbols: bool,
+    /// Draw lines between beads
+    pub grid: bool,
+    /// Every how many beads the grid lines are heavier
+    pub grid_every: u32,
+    /// Number the rows and columns along the top and left edges
+    pub coordinates: bool,
+}
+
+/// Size of a bead in SVG user units
+const PITCH: f32 = 10.;
+/// Room for the row and column numbers
+const LABEL_SPACE: f32 = 12.;
+
+/// Draws the pattern as SVG with an element for each bead
+///
+/// Beads are grouped by colour with the colour name as title, so all beads of a colour
+/// can be selected and changed together in an editor.
+pub fn pattern_svg(pattern: &BeadPattern, palette: &Palette, options: &SvgOptions) -> String {
+    let (width, height) = pattern.beads.dimensions();
+    let margin = if options.coordinates { LABEL_SPACE } else { 0. };
+    let view_width = width as f32 * PITCH + margin;
+    let view_height = height as f32 * PITCH + margin;
+    let mm_per_unit = options.bead_size.pitch_mm() / PITCH;
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.2}mm" height="{:.2}mm" viewBox="{} {} {view_width} {view_height}">"#,
+        view_width * mm_per_unit,
+        view_height * mm_per_unit,
+        -margin,
+        -margin,
+    );
+
+    let mut positions = vec![Vec::new(); palette.len()];
+    for y in 0..height {
+        for x in 0..width {
+            if let Some(i) = pattern.palette_index(x, y) {
+                positions[i].push((x as f32 * PITCH, y as f32 * PITCH));
+            }
+        }
+    }
+
+    let colours = palette.colours();
+    let _ = writeln!(svg, r#"<g id="beads">"#);
+    for (i, beads) in positions.iter().enumerate().filter(|(_, b)| !b.is_empty()) {
+        let (name, colour) = &colours[i];
+        let _ = writeln!(
+            svg,
+            r#"<g id="colour-{i}" fill="{}"><title>{}</title>"#,
+            hex_name(*colour),
+            escape(name)
+        );
+        for &(x, y) in beads {
+            let _ = match options.shape {
+                BeadShape::Circle => writeln!(
+                    svg,
+                    r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+                    x + PITCH / 2.,
+                    y + PITCH / 2.,
+                    PITCH * 0.45
+                ),
+                BeadShape::Square => writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}"/>"#,
+                    x + PITCH * 0.05,
+                    y + PITCH * 0.05,
+                    PITCH * 0.9,
+                    PITCH * 0.9,
+                    PITCH * 0.2
+                ),
+            };
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+    let _ = writeln!(svg, "</g>");
+
+    if options.symbols {
+        let symbols = Symbols::allocate(&pattern.frequency, palette);
+        let _ = writeln!(
+            svg,
+            r#"<g id="symbols" font-family="Helvetica, Arial, sans-serif" font-size="{}" text-anchor="middle" dominant-baseline="central">"#,
+            PITCH * 0.55
+        );
+        for (i, beads) in positions.iter().enumerate().filter(|(_, b)| !b.is_empty()) {
+            let Some(symbol) = symbols.get(i) else {
+                continue;
+            };
+            let length = symbol.text.chars().count();
+            if length > 2 {
+                // Shrink symbols longer than the usual two characters to fit in the bead
+                let _ = writeln!(
+                    svg,
+                    r#"<g fill="{}" font-size="{}">"#,
+                    hex_name(symbol.colour),
+                    PITCH * 0.55 * 2. / length as f32
+                );
+            } else {
+                let _ = writeln!(svg, r#"<g fill="{}">"#, hex_name(symbol.colour));
+            }
+            for &(x, y) in beads {
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}">{}</text>"#,
+                    x + PITCH / 2.,
+                    y + PITCH / 2.,
+                    escape(&symbol.text)
+                );
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    if options.grid {
+        let (grid_width, grid_height) = (width as f32 * PITCH, height as f32 * PITCH);
+        let _ = writeln!(svg, r##"<g id="grid" stroke="#666666" fill="none">"##);
+        for x in 0..=width {
+            let line_x = x as f32 * PITCH;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{line_x}" y1="0" x2="{line_x}" y2="{grid_height}" stroke-width="{}"/>"#,
+                line_width(x, options.grid_every)
+            );
+        }
+        for y in 0..=height {
+            let line_y = y as f32 * PITCH;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="0" y1="{line_y}" x2="{grid_width}" y2="{line_y}" stroke-width="{}"/>"#,
+                line_width(y, options.grid_every)
+            );
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    if options.coordinates {
+        let _ = writeln!(
+            svg,
+            r#"<g id="coordinates" font-family="Helvetica, Arial, sans-serif" font-size="{}" fill="black">"#,
+            PITCH * 0.4
+        );
+        for x in (1..=width).filter(|&n| is_labelled(n, options.grid_every)) {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" text-anchor="middle">{x}</text>"#,
+                (x as f32 - 0.5) * PITCH,
+                -LABEL_SPACE / 3.
+            );
+        }
+        for y in (1..=height).filter(|&n| is_labelled(n, options.grid_every)) {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" text-anchor="end" dominant-baseline="central">{y}</text>"#,
+                -LABEL_SPACE / 4.,
+                (y as f32 - 0.5) * PITCH
+            );
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn line_width(n: u32, every: u32) -> f32 {
+    match line_weight(n, every) {
+        LineWeight::Thin => 0.15,
+        LineWeight::Medium => 0.4,
+        LineWeight::Heavy => 0.8,
+    }
+}
+
+/// Escapes text for XML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
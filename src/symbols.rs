@@ -0,0 +1,167 @@
+//! Symbols telling the colours of a pattern apart without relying on colour,
+//! for printing in greyscale and for colour blind readers
+
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use image::{Rgb, Rgba, RgbaImage};
+
+use crate::{
+    font::{draw_text, text_width, GLYPH_HEIGHT},
+    palette::Palette,
+};
+
+/// Symbols in the order they are given out, leaving out ones easily confused like `O` and `0`
+const SYMBOLS: &[char] = &[
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'N', 'P', 'R', 'S', 'T', 'U', 'V',
+    'W', 'X', 'Y', 'Z', '2', '3', '4', '5', '6', '7', '8', '9', '+', '=', '#', '%', '&', '*', '@',
+    '?', '<', '>',
+];
+
+/// The symbol of a colour
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// One or more characters
+    pub text: Box<str>,
+    /// Black or white, whichever contrasts most with the bead colour
+    pub colour: Rgb<u8>,
+}
+
+/// Symbols for the palette colours used in a pattern
+#[derive(Debug, Clone)]
+pub struct Symbols {
+    symbols: Vec<Option<Symbol>>,
+}
+
+impl Symbols {
+    /// Gives each colour in the bead `frequency` its own symbol
+    ///
+    /// The most used colours get the single characters first, so the longer symbols
+    /// needed beyond them go to the least used colours.
+    pub fn allocate(frequency: &BTreeMap<&str, u32>, palette: &Palette) -> Self {
+        let colours = palette.colours();
+        let mut used: Vec<_> = colours
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, _))| Some((i, *frequency.get(&**name)?)))
+            .collect();
+        // Sorting is stable, so colours with the same count stay in palette order
+        used.sort_by_key(|&(_, count)| Reverse(count));
+
+        let mut symbols = vec![None; colours.len()];
+        for (n, (i, _)) in used.into_iter().enumerate() {
+            symbols[i] = Some(Symbol {
+                text: symbol_text(n).into(),
+                colour: contrasting_colour(colours[i].1),
+            });
+        }
+        Symbols { symbols }
+    }
+    /// The symbol of the palette colour at the index, `None` if the colour isn't used
+    pub fn get(&self, palette_index: usize) -> Option<&Symbol> {
+        self.symbols.get(palette_index)?.as_ref()
+    }
+}
+
+/// The `n`th symbol counting from 0, after all symbols of one character come all of two and so on
+fn symbol_text(n: usize) -> String {
+    let mut chars = Vec::new();
+    let mut n = n + 1;
+    while n > 0 {
+        n -= 1;
+        chars.push(SYMBOLS[n % SYMBOLS.len()]);
+        n /= SYMBOLS.len();
+    }
+    chars.iter().rev().collect()
+}
+
+/// Black or white, whichever has the higher contrast ratio against the background
+pub fn contrasting_colour(background: Rgb<u8>) -> Rgb<u8> {
+    let l = luminance(background);
+    if (l + 0.05) / 0.05 > 1.05 / (l + 0.05) {
+        Rgb([0, 0, 0])
+    } else {
+        Rgb([255, 255, 255])
+    }
+}
+
+/// Relative luminance of an sRGB colour from 0 to 1
+fn luminance(Rgb([r, g, b]): Rgb<u8>) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Writes the symbol of each bead onto an image of the beads scaled up,
+/// `palette_index` giving the colour of the bead at each position of `beads`
+///
+/// Nothing is drawn if the beads are too small to fit a symbol.
+pub fn draw_symbols(
+    img: &mut RgbaImage,
+    beads: &RgbaImage,
+    symbols: &Symbols,
+    palette_index: impl Fn(u32, u32) -> Option<usize>,
+) {
+    let cell = img.width() / beads.width().max(1);
+    for y in 0..beads.height() {
+        for x in 0..beads.width() {
+            let Some(symbol) = palette_index(x, y).and_then(|i| symbols.get(i)) else {
+                continue;
+            };
+            // Leave at least a font pixel free on each side
+            let width = text_width(&symbol.text);
+            let scale = (cell / (width + 2)).min(cell / (GLYPH_HEIGHT + 2));
+            if scale == 0 {
+                continue;
+            }
+            let left = (x * cell + (cell - width * scale) / 2) as i64;
+            let top = (y * cell + (cell - GLYPH_HEIGHT * scale) / 2) as i64;
+            let Rgb([r, g, b]) = symbol.colour;
+            // A halo in the opposite colour keeps the symbol readable on shading and holes in the beads
+            let halo = Rgba([255 - r, 255 - g, 255 - b, 255]);
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                draw_text(img, left + dx, top + dy, scale, halo, &symbol.text);
+            }
+            draw_text(img, left, top, scale, Rgba([r, g, b, 255]), &symbol.text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_grow_longer_when_running_out() {
+        let base = SYMBOLS.len();
+        assert_eq!(symbol_text(0), "A");
+        assert_eq!(symbol_text(base - 1), ">");
+        assert_eq!(symbol_text(base), "AA");
+        assert_eq!(symbol_text(2 * base), "BA");
+        assert_eq!(symbol_text(base + base * base - 1), ">>");
+        assert_eq!(symbol_text(base + base * base), "AAA");
+    }
+
+    #[test]
+    fn every_colour_gets_its_own_symbol() {
+        let names: Vec<_> = (0..5000).map(|i| format!("c{i}")).collect();
+        let palette = Palette::new(
+            names
+                .iter()
+                .map(|name| (name.as_str().into(), Rgb([0, 0, 0])))
+                .collect(),
+        );
+        let frequency = names.iter().map(|name| (name.as_str(), 1)).collect();
+
+        let symbols = Symbols::allocate(&frequency, &palette);
+        let mut texts: Vec<_> = (0..5000).map(|i| &symbols.get(i).unwrap().text).collect();
+        texts.sort();
+        texts.dedup();
+        assert_eq!(texts.len(), 5000);
+    }
+}